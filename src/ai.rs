@@ -0,0 +1,571 @@
+// (C) Copyright 2023 Ars Militaris Dev
+
+// Server-side AI.
+//
+// Units of the teams controlled by the AI (see `Game::players`) play their
+// turns on the server. When such a unit gets its turn, the AI waits a moment
+// so that the clients can show the start of the turn, plans the turn, and
+// enqueues the unit's actions the same way the actions of a client are
+// enqueued. Once the actions are done, it ends the turn.
+//
+// How the AI plays a unit depends on the unit's AI profile, given in the
+// battle CSV:
+//
+// - "aggressive" (or nothing): attacks the enemy with the least HP it can
+//   reach this turn, moving as little as it can to do so. If it can't reach
+//   any enemy, it moves as close as it can to the nearest one.
+// - "hold": doesn't move, and attacks the enemies in reach of its tile.
+// - "support": heals the ally missing the most HP it can reach, if it has a
+//   healing skill and the MP to use it. Otherwise, it is aggressive.
+// - "flee": runs as far as it can from the enemies once it has less than
+//   `FLEE_HP_PERCENT` of its HP left. Otherwise, it is aggressive.
+// - "guard:<x>:<y>": stays within `GUARD_RADIUS` tiles of the tile at x, y,
+//   and attacks the enemies it can reach from there.
+
+use bevy::prelude::*;
+use bevy::ecs::system::SystemParam;
+
+use crate::{
+	end_turn, find_possible_attacks, movement_points, AttackRange, AttackType, Attacker, BattleRules, CombatMessages,
+	ControlledBy, CurrentUnit, GameState, HPCurrent, HPMax, Jump, MPCurrent, MovementRange, Pos, ServerMessage, Skills,
+	Target, UnitAction, UnitActionTuple, UnitActions, UnitTeam, WTCurrent, WTMax,
+};
+use crate::battle_grid::BattleGrid;
+use crate::skills::{SkillBook, SkillEffect};
+use crate::sessions::SessionServer;
+use crate::status::StatusEffects;
+use crate::turn_order::TurnActions;
+
+// Seconds the AI waits before acting, and after it has acted before ending
+// its turn.
+const THINKING_TIME: f32 = 1.0;
+const ACTING_TIME: f32 = 2.0;
+
+// Percentage of its max HP under which a fleeing unit runs away.
+const FLEE_HP_PERCENT: usize = 30;
+
+// Farthest a guarding unit goes from the tile it guards.
+const GUARD_RADIUS: usize = 3;
+
+pub struct AiPlugin;
+
+impl Plugin for AiPlugin {
+	fn build(&self, app: &mut App) {
+		app.add_systems(Update, (apply_deferred, take_ai_turns, apply_deferred)
+			.chain()
+			.run_if(in_state(GameState::Battle))
+		);
+	}
+}
+
+#[derive(Component, Debug, Clone, Copy, PartialEq, Default)]
+pub enum AiProfile {
+	#[default]
+	Aggressive,
+	Hold,
+	Support,
+	Flee,
+	Guard { tile: Pos },
+}
+
+impl AiProfile {
+	// Parse an AI profile as written in the battle CSV.
+	pub fn from_string(string: &str) -> Result<AiProfile, String> {
+		match string.split(':').collect::<Vec<&str>>().as_slice() {
+			[""] | ["aggressive"] => Ok(AiProfile::Aggressive),
+			["hold"] => Ok(AiProfile::Hold),
+			["support"] => Ok(AiProfile::Support),
+			["flee"] => Ok(AiProfile::Flee),
+			["guard", x, y] => match (x.parse(), y.parse()) {
+				(Ok(x), Ok(y)) => Ok(AiProfile::Guard { tile: Pos { x, y, }, }),
+				_ => Err(format!("invalid guarded tile in AI profile {:?}", string)),
+			},
+			_ => Err(format!("unknown AI profile {:?}", string)),
+		}
+	}
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum AiPhase {
+	Thinking,
+	Acting,
+}
+
+// The turn an AI unit is playing.
+#[derive(Component)]
+pub struct AiTurn {
+	phase: AiPhase,
+	timer: Timer,
+}
+
+// The unit the AI plans a turn for.
+pub struct AiUnit {
+	pub pos: Pos,
+	pub hp: usize,
+	pub hp_max: usize,
+	pub mp: usize,
+	pub skill_ids: Vec<String>,
+	pub movement_points: usize,
+	pub jump: usize,
+	pub attack_range: isize,
+	pub attack_type: AttackType,
+	// What the unit has already done this turn.
+	pub turn_actions: TurnActions,
+}
+
+// What the AI plans against. `allies` include the unit the AI plans for.
+pub struct Battlefield<'a> {
+	pub grid: &'a BattleGrid,
+	pub skill_book: &'a SkillBook,
+	pub units_block_line_of_sight: bool,
+	// Positions, HP and max HP.
+	pub allies: Vec<(Pos, usize, usize)>,
+	// Positions and HP.
+	pub enemies: Vec<(Pos, usize)>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum AiAction {
+	BasicAttack { target: Pos },
+	UseSkill { skill_id: String, target: Pos },
+}
+
+// What the AI does with a unit in its turn.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct AiPlan {
+	pub destination: Option<Pos>,
+	pub action: Option<AiAction>,
+}
+
+// The current unit, with the AI turn it is in the middle of, if any.
+type AiUnitQuery<'w, 's> = Query<'w, 's, (Entity, &'static mut UnitActions, &'static mut WTCurrent, &'static WTMax, &'static Pos, &'static UnitTeam, &'static MovementRange, &'static Jump, &'static AttackRange, &'static AttackType, &'static StatusEffects, &'static mut TurnActions, Option<&'static mut AiTurn>), With<CurrentUnit>>;
+
+// What the AI plans with.
+#[derive(SystemParam)]
+struct AiContext<'w, 's> {
+	rules: BattleRules<'w, 's>,
+	time: Res<'w, Time>,
+	ai_unit_query: Query<'w, 's, (&'static AiProfile, &'static HPCurrent, &'static HPMax, &'static MPCurrent, &'static Skills), With<CurrentUnit>>,
+	units: Query<'w, 's, (&'static Pos, &'static UnitTeam, &'static HPCurrent, &'static HPMax)>,
+}
+
+// Server
+fn take_ai_turns(
+mut commands: Commands,
+context: AiContext,
+mut current_unit_query: AiUnitQuery,
+mut server: ResMut<SessionServer>,
+mut combat_messages: ResMut<CombatMessages>,
+mut next_state: ResMut<NextState<GameState>>,
+) {
+	let Ok((entity, mut unit_actions, mut wt_current, wt_max, pos, unit_team, movement_range, jump, attack_range, attack_type, status_effects, mut turn_actions, ai_turn)) = current_unit_query.get_single_mut() else {
+		return;
+	};
+	if context.rules.game.players.get(&unit_team.value) != Some(&ControlledBy::AI) {
+		return;
+	}
+
+	let Some(mut ai_turn) = ai_turn else {
+		info!("DEBUG: AI is taking unit turn.");
+		commands.entity(entity).insert(AiTurn { phase: AiPhase::Thinking, timer: Timer::from_seconds(THINKING_TIME, TimerMode::Once), });
+		return;
+	};
+	if !ai_turn.timer.tick(context.time.delta()).finished() {
+		return;
+	}
+
+	let endpoint = server.endpoint_mut();
+	let grid = &context.rules.grid;
+
+	match ai_turn.phase {
+		AiPhase::Thinking => {
+			// The unit may have started its turn before its team was given
+			// to the AI. Plan from where the unit ends up, once it is done
+			// with its actions.
+			if unit_actions.is_busy() {
+				return;
+			}
+
+			let Ok((ai_profile, hp_current, hp_max, mp_current, skills)) = context.ai_unit_query.get_single() else {
+				return;
+			};
+
+			let mut battlefield = Battlefield {
+				grid,
+				skill_book: &context.rules.skill_book,
+				units_block_line_of_sight: context.rules.config.battle.units_block_line_of_sight,
+				allies: Vec::new(),
+				enemies: Vec::new(),
+			};
+			for (unit_pos, team, unit_hp_current, unit_hp_max) in context.units.iter() {
+				if team.value == unit_team.value {
+					battlefield.allies.push((*unit_pos, unit_hp_current.value, unit_hp_max.value));
+				} else {
+					battlefield.enemies.push((*unit_pos, unit_hp_current.value));
+				}
+			}
+			let ai_unit = AiUnit {
+				pos: *pos,
+				hp: hp_current.value,
+				hp_max: hp_max.value,
+				mp: mp_current.value,
+				skill_ids: skills.skill_ids.clone(),
+				movement_points: movement_points(movement_range, status_effects),
+				jump: jump.value,
+				attack_range: attack_range.value,
+				attack_type: *attack_type,
+				turn_actions: *turn_actions,
+			};
+			let plan = plan_turn(&battlefield, &ai_unit, ai_profile);
+			info!("DEBUG: AI plan is {:?}.", plan);
+
+			// Insert `Move` `UnitAction` in the unit, and send `Move` message
+			// to clients.
+			if let Some(destination) = plan.destination {
+				turn_actions.moved = true;
+				unit_actions.unit_actions.push(UnitActionTuple(UnitAction::Move {
+					origin: *pos,
+					destination,
+					timer: Timer::from_seconds(4.0, TimerMode::Once),
+				}, 0.0));
+				endpoint.broadcast_message(ServerMessage::Move {
+					origin: *pos,
+					destination,
+				}).unwrap();
+			}
+
+			match plan.action {
+				// Insert `BasicAttack` `UnitAction` in the unit, and the
+				// `Attacker` and `Target` markers.
+				Some(AiAction::BasicAttack { target }) => {
+					if let Some(target_entity) = grid.occupant(target) {
+						turn_actions.acted = true;
+						unit_actions.unit_actions.push(UnitActionTuple(UnitAction::BasicAttack {
+							target,
+						}, 0.0));
+						commands.entity(entity).insert(Attacker {});
+						commands.entity(target_entity).insert(Target {});
+					}
+				},
+				// Insert `UseSkill` `UnitAction` in the unit.
+				Some(AiAction::UseSkill { skill_id, target }) => {
+					turn_actions.acted = true;
+					unit_actions.unit_actions.push(UnitActionTuple(UnitAction::UseSkill {
+						skill_id,
+						target,
+					}, 0.0));
+				},
+				None => {},
+			}
+
+			ai_turn.phase = AiPhase::Acting;
+			ai_turn.timer = Timer::from_seconds(ACTING_TIME, TimerMode::Once);
+		},
+		AiPhase::Acting => {
+			// Wait for the unit to be done with its actions.
+			if unit_actions.is_busy() {
+				return;
+			}
+
+			wt_current.value = turn_actions.wt_cost(wt_max.value);
+			info!("DEBUG: Reseted Current Unit's WT. It is now: {:?}.", wt_current);
+			commands.entity(entity).remove::<AiTurn>();
+			end_turn(endpoint, &mut commands, entity, &mut combat_messages, &mut next_state);
+		},
+	}
+}
+
+// Server
+// Plan the rest of the turn of `unit`, the way its AI profile plays.
+pub fn plan_turn(battlefield: &Battlefield, unit: &AiUnit, profile: &AiProfile) -> AiPlan {
+	let destinations = destinations(battlefield.grid, unit);
+
+	match profile {
+		AiProfile::Aggressive => attack_or_approach(battlefield, unit, &destinations),
+		AiProfile::Hold => plan_attack(battlefield, unit, &[unit.pos]).unwrap_or_default(),
+		AiProfile::Support => plan_heal(battlefield, unit, &destinations)
+			.unwrap_or_else(|| attack_or_approach(battlefield, unit, &destinations)),
+		AiProfile::Flee => {
+			if unit.hp * 100 < unit.hp_max * FLEE_HP_PERCENT {
+				move_to_best(unit, &destinations, |destination| std::cmp::Reverse(distance_to_nearest(destination, &battlefield.enemies)))
+			} else {
+				attack_or_approach(battlefield, unit, &destinations)
+			}
+		},
+		AiProfile::Guard { tile } => {
+			let guarded: Vec<Pos> = destinations.iter()
+				.filter(|destination| distance(destination, tile) <= GUARD_RADIUS)
+				.copied()
+				.collect();
+			plan_attack(battlefield, unit, &guarded)
+				.unwrap_or_else(|| move_to_best(unit, &destinations, |destination| distance(destination, tile)))
+		},
+	}
+}
+
+// Server
+// The tiles the unit can end its move on, staying put first, then the
+// cheapest ones. Ties are broken by position, so that the AI always plays the
+// same way in the same situation.
+fn destinations(grid: &BattleGrid, unit: &AiUnit) -> Vec<Pos> {
+	if unit.turn_actions.moved {
+		return vec![unit.pos];
+	}
+
+	let mut destinations: Vec<(Pos, usize)> = grid.reachable_tiles(unit.pos, unit.movement_points, unit.jump).destinations().collect();
+	destinations.sort_by_key(|(destination, cost)| (*cost, destination.x, destination.y));
+
+	std::iter::once(unit.pos)
+		.chain(destinations.into_iter().map(|(destination, _)| destination))
+		.collect()
+}
+
+// Server
+// Attack the weakest enemy in reach, or get as close as possible to the
+// nearest one.
+fn attack_or_approach(battlefield: &Battlefield, unit: &AiUnit, destinations: &[Pos]) -> AiPlan {
+	plan_attack(battlefield, unit, destinations)
+		.unwrap_or_else(|| move_to_best(unit, destinations, |destination| distance_to_nearest(destination, &battlefield.enemies)))
+}
+
+// Server
+// Attack the enemy with the least HP that the unit can reach from one of
+// `destinations`, moving to the first of them it can attack from.
+fn plan_attack(battlefield: &Battlefield, unit: &AiUnit, destinations: &[Pos]) -> Option<AiPlan> {
+	if unit.turn_actions.acted {
+		return None;
+	}
+
+	let mut best_attack: Option<(usize, Pos, Pos)> = None;
+	for destination in destinations.iter() {
+		let attackable = find_possible_attacks(battlefield.grid, *destination, unit.attack_range, unit.attack_type, battlefield.units_block_line_of_sight);
+		for (enemy_pos, enemy_hp) in battlefield.enemies.iter() {
+			if attackable.contains(enemy_pos) && best_attack.is_none_or(|(best_hp, _, _)| *enemy_hp < best_hp) {
+				best_attack = Some((*enemy_hp, *destination, *enemy_pos));
+			}
+		}
+	}
+
+	best_attack.map(|(_, destination, target)| AiPlan {
+		destination: Some(destination).filter(|destination| *destination != unit.pos),
+		action: Some(AiAction::BasicAttack { target, }),
+	})
+}
+
+// Server
+// Heal the ally missing the most HP, with the first healing skill the unit
+// has the MP for.
+fn plan_heal(battlefield: &Battlefield, unit: &AiUnit, destinations: &[Pos]) -> Option<AiPlan> {
+	if unit.turn_actions.acted {
+		return None;
+	}
+
+	let skill = unit.skill_ids.iter()
+		.filter_map(|skill_id| battlefield.skill_book.get(skill_id))
+		.find(|skill| skill.effect == SkillEffect::Heal && skill.mp_cost <= unit.mp)?;
+
+	let mut best_heal: Option<(usize, Pos, Pos)> = None;
+	for destination in destinations.iter() {
+		let reach = skill.range.reach(battlefield.grid, *destination);
+		for (ally_pos, ally_hp, ally_hp_max) in battlefield.allies.iter() {
+			// The unit can heal itself, wherever it moves to.
+			let ally_pos = if *ally_pos == unit.pos { *destination } else { *ally_pos };
+			let missing_hp = ally_hp_max.saturating_sub(*ally_hp);
+			if missing_hp > 0 && reach.contains(&ally_pos) && best_heal.is_none_or(|(best_missing_hp, _, _)| missing_hp > best_missing_hp) {
+				best_heal = Some((missing_hp, *destination, ally_pos));
+			}
+		}
+	}
+
+	best_heal.map(|(_, destination, target)| AiPlan {
+		destination: Some(destination).filter(|destination| *destination != unit.pos),
+		action: Some(AiAction::UseSkill { skill_id: skill.id.clone(), target, }),
+	})
+}
+
+// Server
+// Move to the destination with the lowest `key`, without acting.
+fn move_to_best<K: Ord>(unit: &AiUnit, destinations: &[Pos], key: impl Fn(&Pos) -> K) -> AiPlan {
+	let best = destinations.iter().min_by_key(|destination| key(destination));
+
+	AiPlan {
+		destination: best.copied().filter(|destination| *destination != unit.pos),
+		action: None,
+	}
+}
+
+// Utility
+fn distance(from: &Pos, to: &Pos) -> usize {
+	from.x.abs_diff(to.x) + from.y.abs_diff(to.y)
+}
+
+// Utility
+fn distance_to_nearest(from: &Pos, enemies: &[(Pos, usize)]) -> usize {
+	enemies.iter()
+		.map(|(enemy_pos, _)| distance(from, enemy_pos))
+		.min()
+		.unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	use bevy::ecs::entity::Entity;
+
+	use crate::TileType;
+	use crate::battle_map::{BattleMap, MapCell};
+
+	// A flat 5x5 grass grid, with a unit on each tile of `occupied`.
+	fn grid(occupied: &[Pos]) -> BattleGrid {
+		let cells = (0..5)
+			.map(|_| (0..5)
+				.map(|_| MapCell { height: 0, tile_type: TileType::Grass, passable: true, spawn_team: None, })
+				.collect())
+			.collect();
+		let mut grid = BattleGrid::from_battle_map(&BattleMap { width: 5, height: 5, cells, });
+		for (i, pos) in occupied.iter().enumerate() {
+			grid.place(*pos, Entity::from_raw(i as u32));
+		}
+		grid
+	}
+
+	// A healthy melee unit on (0, 0), that knows the "heal" skill and hasn't
+	// done anything yet this turn.
+	fn unit(movement_points: usize) -> AiUnit {
+		AiUnit {
+			pos: Pos { x: 0, y: 0 },
+			hp: 100,
+			hp_max: 100,
+			mp: 10,
+			skill_ids: vec!["heal".to_string()],
+			movement_points,
+			jump: 1,
+			attack_range: 1,
+			attack_type: AttackType::Melee,
+			turn_actions: TurnActions::default(),
+		}
+	}
+
+	// Plan the turn of `unit` with `profile`, against `enemies` and along
+	// with `allies`, all standing on the grid.
+	fn plan(unit: &AiUnit, profile: AiProfile, allies: &[(Pos, usize, usize)], enemies: &[(Pos, usize)]) -> AiPlan {
+		let mut occupied = vec![unit.pos];
+		occupied.extend(allies.iter().map(|(pos, _, _)| *pos));
+		occupied.extend(enemies.iter().map(|(pos, _)| *pos));
+		let grid = grid(&occupied);
+		let skill_book = SkillBook::from_file("src/skills.toml").unwrap();
+
+		let mut battlefield = Battlefield {
+			grid: &grid,
+			skill_book: &skill_book,
+			units_block_line_of_sight: false,
+			allies: vec![(unit.pos, unit.hp, unit.hp_max)],
+			enemies: enemies.to_vec(),
+		};
+		battlefield.allies.extend_from_slice(allies);
+		plan_turn(&battlefield, unit, &profile)
+	}
+
+	fn attack(destination: Option<Pos>, target: Pos) -> AiPlan {
+		AiPlan { destination, action: Some(AiAction::BasicAttack { target, }), }
+	}
+
+	fn move_to(destination: Pos) -> AiPlan {
+		AiPlan { destination: Some(destination), action: None, }
+	}
+
+	#[test]
+	fn reads_ai_profiles() {
+		assert_eq!(AiProfile::from_string(""), Ok(AiProfile::Aggressive));
+		assert_eq!(AiProfile::from_string("aggressive"), Ok(AiProfile::Aggressive));
+		assert_eq!(AiProfile::from_string("hold"), Ok(AiProfile::Hold));
+		assert_eq!(AiProfile::from_string("support"), Ok(AiProfile::Support));
+		assert_eq!(AiProfile::from_string("flee"), Ok(AiProfile::Flee));
+		assert_eq!(AiProfile::from_string("guard:2:3"), Ok(AiProfile::Guard { tile: Pos { x: 2, y: 3 }, }));
+		assert!(AiProfile::from_string("guard:2").is_err());
+		assert!(AiProfile::from_string("guard:2:north").is_err());
+		assert!(AiProfile::from_string("berserk").is_err());
+	}
+
+	#[test]
+	fn aggressive_units_attack_without_moving_when_they_can() {
+		let plan = plan(&unit(3), AiProfile::Aggressive, &[], &[(Pos { x: 1, y: 0 }, 50)]);
+
+		assert_eq!(plan, attack(None, Pos { x: 1, y: 0 }));
+	}
+
+	#[test]
+	fn aggressive_units_move_to_attack_the_weakest_enemy_in_reach() {
+		let plan = plan(&unit(3), AiProfile::Aggressive, &[], &[(Pos { x: 1, y: 0 }, 50), (Pos { x: 0, y: 3 }, 20), (Pos { x: 4, y: 4 }, 10)]);
+
+		assert_eq!(plan, attack(Some(Pos { x: 0, y: 2 }), Pos { x: 0, y: 3 }));
+	}
+
+	#[test]
+	fn aggressive_units_close_in_on_the_nearest_enemy_out_of_reach() {
+		let plan = plan(&unit(2), AiProfile::Aggressive, &[], &[(Pos { x: 4, y: 4 }, 50)]);
+
+		assert_eq!(plan, move_to(Pos { x: 0, y: 2 }));
+	}
+
+	#[test]
+	fn holding_units_only_attack_from_their_tile() {
+		assert_eq!(plan(&unit(3), AiProfile::Hold, &[], &[(Pos { x: 1, y: 0 }, 50)]), attack(None, Pos { x: 1, y: 0 }));
+		assert_eq!(plan(&unit(3), AiProfile::Hold, &[], &[(Pos { x: 0, y: 2 }, 50)]), AiPlan::default());
+	}
+
+	#[test]
+	fn support_units_heal_the_ally_missing_the_most_hp() {
+		let allies = [(Pos { x: 2, y: 0 }, 50, 100), (Pos { x: 0, y: 2 }, 90, 100)];
+		let heal = AiPlan { destination: None, action: Some(AiAction::UseSkill { skill_id: "heal".to_string(), target: Pos { x: 2, y: 0 }, }), };
+		assert_eq!(plan(&unit(3), AiProfile::Support, &allies, &[(Pos { x: 4, y: 4 }, 50)]), heal);
+
+		// Without the MP to heal, or anyone to heal, they are aggressive.
+		let tired = AiUnit { mp: 5, ..unit(2) };
+		assert_eq!(plan(&tired, AiProfile::Support, &allies, &[(Pos { x: 4, y: 4 }, 50)]), move_to(Pos { x: 1, y: 1 }));
+		assert_eq!(plan(&unit(3), AiProfile::Support, &[(Pos { x: 2, y: 0 }, 100, 100)], &[(Pos { x: 1, y: 0 }, 50)]), attack(None, Pos { x: 1, y: 0 }));
+	}
+
+	#[test]
+	fn support_units_heal_themselves() {
+		let hurt = AiUnit { hp: 40, ..unit(3) };
+		let plan = plan(&hurt, AiProfile::Support, &[], &[(Pos { x: 1, y: 0 }, 50)]);
+
+		assert_eq!(plan.action, Some(AiAction::UseSkill { skill_id: "heal".to_string(), target: Pos { x: 0, y: 0 }, }));
+		assert_eq!(plan.destination, None);
+	}
+
+	#[test]
+	fn fleeing_units_run_away_once_hurt() {
+		let enemies = [(Pos { x: 1, y: 0 }, 50)];
+		assert_eq!(plan(&unit(2), AiProfile::Flee, &[], &enemies), attack(None, Pos { x: 1, y: 0 }));
+
+		let hurt = AiUnit { hp: 20, ..unit(2) };
+		assert_eq!(plan(&hurt, AiProfile::Flee, &[], &enemies), move_to(Pos { x: 0, y: 2 }));
+	}
+
+	#[test]
+	fn guarding_units_stay_near_their_tile() {
+		let enemies = [(Pos { x: 3, y: 2 }, 50)];
+		assert_eq!(plan(&unit(5), AiProfile::Aggressive, &[], &enemies).action, Some(AiAction::BasicAttack { target: Pos { x: 3, y: 2 }, }));
+		assert_eq!(plan(&unit(5), AiProfile::Guard { tile: Pos { x: 0, y: 0 }, }, &[], &enemies), AiPlan::default());
+
+		// Away from their tile, they go back to it.
+		assert_eq!(plan(&unit(2), AiProfile::Guard { tile: Pos { x: 4, y: 0 }, }, &[], &[(Pos { x: 0, y: 4 }, 50)]), move_to(Pos { x: 2, y: 0 }));
+
+		// Enemies near their tile are attacked.
+		assert_eq!(plan(&unit(5), AiProfile::Guard { tile: Pos { x: 2, y: 2 }, }, &[], &enemies), attack(Some(Pos { x: 2, y: 2 }), Pos { x: 3, y: 2 }));
+	}
+
+	#[test]
+	fn units_play_only_what_is_left_of_their_turn() {
+		let moved = AiUnit { turn_actions: TurnActions { moved: true, acted: false, }, ..unit(3) };
+		assert_eq!(plan(&moved, AiProfile::Aggressive, &[], &[(Pos { x: 0, y: 2 }, 10)]), AiPlan::default());
+		assert_eq!(plan(&moved, AiProfile::Aggressive, &[], &[(Pos { x: 1, y: 0 }, 10)]), attack(None, Pos { x: 1, y: 0 }));
+
+		let acted = AiUnit { turn_actions: TurnActions { moved: false, acted: true, }, ..unit(3) };
+		assert_eq!(plan(&acted, AiProfile::Aggressive, &[], &[(Pos { x: 0, y: 4 }, 10)]), move_to(Pos { x: 0, y: 3 }));
+		assert_eq!(plan(&AiUnit { hp: 40, ..acted }, AiProfile::Support, &[], &[(Pos { x: 0, y: 4 }, 10)]), move_to(Pos { x: 0, y: 3 }));
+	}
+}
@@ -0,0 +1,532 @@
+// (C) Copyright 2023 Ars Militaris Dev
+
+use bevy::prelude::*;
+
+use crate::{Pos, TileType};
+use crate::battle_map::BattleMap;
+
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap};
+
+// Height, above the ground, from which units shoot and at which they are
+// aimed at.
+const SHOT_HEIGHT: f32 = 1.0;
+
+// Height of a unit standing on a tile, when units block shots.
+const UNIT_HEIGHT: f32 = 2.0;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TileFlags {
+	pub passable: bool,
+	pub spawn_team: Option<usize>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Tile {
+	pub height: usize,
+	pub terrain: TileType,
+	pub occupant: Option<Entity>,
+	pub flags: TileFlags,
+}
+
+// The battlefield of the current battle.
+//
+// Tiles are addressed by `Pos`, with x growing to the East and y growing to
+// the North. All accessors are bounds-checked, and return `None` (or
+// `false`) for positions outside of the grid.
+#[derive(Resource, Debug, Clone)]
+pub struct BattleGrid {
+	width: usize,
+	height: usize,
+	tiles: Vec<Tile>,
+}
+
+impl BattleGrid {
+	pub fn from_battle_map(battle_map: &BattleMap) -> BattleGrid {
+		let mut tiles = Vec::with_capacity(battle_map.width * battle_map.height);
+		for column in &battle_map.cells {
+			for cell in column {
+				tiles.push(Tile {
+					height: cell.height,
+					terrain: cell.tile_type.clone(),
+					occupant: None,
+					flags: TileFlags {
+						passable: cell.passable,
+						spawn_team: cell.spawn_team,
+					},
+				});
+			}
+		}
+
+		BattleGrid {
+			width: battle_map.width,
+			height: battle_map.height,
+			tiles,
+		}
+	}
+
+	pub fn width(&self) -> usize {
+		self.width
+	}
+
+	pub fn height(&self) -> usize {
+		self.height
+	}
+
+	pub fn in_bounds(&self, pos: Pos) -> bool {
+		pos.x < self.width && pos.y < self.height
+	}
+
+	fn index(&self, pos: Pos) -> Option<usize> {
+		if self.in_bounds(pos) {
+			Some(pos.x * self.height + pos.y)
+		} else {
+			None
+		}
+	}
+
+	pub fn get(&self, pos: Pos) -> Option<&Tile> {
+		self.index(pos).map(|index| &self.tiles[index])
+	}
+
+	pub fn height_at(&self, pos: Pos) -> Option<usize> {
+		self.get(pos).map(|tile| tile.height)
+	}
+
+	pub fn get_mut(&mut self, pos: Pos) -> Option<&mut Tile> {
+		self.index(pos).map(move |index| &mut self.tiles[index])
+	}
+
+	// The up to four tiles orthogonally adjacent to `pos`, in North, South,
+	// East, West order.
+	pub fn neighbors(&self, pos: Pos) -> impl Iterator<Item = Pos> + '_ {
+		let north = Some(Pos { x: pos.x, y: pos.y + 1 });
+		let south = pos.y.checked_sub(1).map(|y| Pos { x: pos.x, y });
+		let east = Some(Pos { x: pos.x + 1, y: pos.y });
+		let west = pos.x.checked_sub(1).map(|x| Pos { x, y: pos.y });
+
+		[north, south, east, west].into_iter()
+			.flatten()
+			.filter(move |neighbor| self.in_bounds(pos) && self.in_bounds(*neighbor))
+	}
+
+	pub fn occupant(&self, pos: Pos) -> Option<Entity> {
+		self.get(pos).and_then(|tile| tile.occupant)
+	}
+
+	pub fn is_occupied(&self, pos: Pos) -> bool {
+		self.occupant(pos).is_some()
+	}
+
+	// A tile is passable if both the map and its terrain allow it.
+	pub fn is_passable(&self, pos: Pos) -> bool {
+		self.get(pos).is_some_and(|tile| tile.flags.passable && tile.terrain.properties().passable)
+	}
+
+	// Movement points spent to step onto the tile.
+	pub fn movement_cost(&self, pos: Pos) -> Option<usize> {
+		self.get(pos).map(|tile| tile.terrain.properties().movement_cost)
+	}
+
+	// Whether a unit can step on the tile: it must be passable and free.
+	pub fn is_walkable(&self, pos: Pos) -> bool {
+		self.is_passable(pos) && !self.is_occupied(pos)
+	}
+
+	// Whether a unit able to climb or drop `jump` height can step from `from`
+	// onto `to`.
+	pub fn can_step(&self, from: Pos, to: Pos, jump: usize) -> bool {
+		match (self.height_at(from), self.height_at(to)) {
+			(Some(from_height), Some(to_height)) => self.is_walkable(to) && from_height.abs_diff(to_height) <= jump,
+			_ => false,
+		}
+	}
+
+	// Tiles a unit standing on `pos` can step on, climbing or dropping at most
+	// `jump` height, with the movement cost of stepping on them.
+	pub fn steps(&self, pos: Pos, jump: usize) -> impl Iterator<Item = (Pos, usize)> + '_ {
+		self.neighbors(pos)
+			.filter(move |neighbor| self.can_step(pos, *neighbor, jump))
+			.map(|neighbor| (neighbor, self.movement_cost(neighbor).unwrap()))
+	}
+
+	// Every tile a unit standing on `start` can reach spending at most
+	// `movement_range` movement points, with the cheapest way to reach it.
+	//
+	// This is a Dijkstra search that stops at `movement_range`, so it only
+	// looks at the tiles around `start`, whatever the size of the grid.
+	pub fn reachable_tiles(&self, start: Pos, movement_range: usize, jump: usize) -> Reachability {
+		let mut tiles = HashMap::new();
+		let mut frontier = BinaryHeap::new();
+		if self.in_bounds(start) {
+			tiles.insert(start, ReachableTile { cost: 0, predecessor: None });
+			frontier.push(Reverse((0, start.x, start.y)));
+		}
+
+		while let Some(Reverse((cost, x, y))) = frontier.pop() {
+			let pos = Pos { x, y };
+			// Skip tiles that were reached more cheaply since they were queued.
+			if tiles[&pos].cost < cost {
+				continue;
+			}
+
+			for (neighbor, step_cost) in self.steps(pos, jump) {
+				let neighbor_cost = cost + step_cost;
+				if neighbor_cost > movement_range {
+					continue;
+				}
+				let is_cheaper = match tiles.get(&neighbor) {
+					Some(tile) => neighbor_cost < tile.cost,
+					None => true,
+				};
+				if is_cheaper {
+					tiles.insert(neighbor, ReachableTile { cost: neighbor_cost, predecessor: Some(pos) });
+					frontier.push(Reverse((neighbor_cost, neighbor.x, neighbor.y)));
+				}
+			}
+		}
+
+		Reachability { start, tiles }
+	}
+
+	// Whether a shot fired from `from` can reach `to`.
+	//
+	// The shot travels in a straight line from `SHOT_HEIGHT` above `from` to
+	// `SHOT_HEIGHT` above `to`. It is blocked by any tile in between whose
+	// ground or terrain rises above the line and, if `units_block` is set, by
+	// any unit standing in the way whose head is above the line.
+	pub fn has_line_of_sight(&self, from: Pos, to: Pos, units_block: bool) -> bool {
+		let (Some(from_height), Some(to_height)) = (self.height_at(from), self.height_at(to)) else {
+			return false;
+		};
+		let from_shot_height = from_height as f32 + SHOT_HEIGHT;
+		let to_shot_height = to_height as f32 + SHOT_HEIGHT;
+
+		// Sample the line twice per tile crossed, so that no tile is skipped.
+		let steps = 2 * from.x.abs_diff(to.x).max(from.y.abs_diff(to.y));
+		for step in 1..steps {
+			let t = step as f32 / steps as f32;
+			let pos = Pos {
+				x: (from.x as f32 + (to.x as f32 - from.x as f32) * t).round() as usize,
+				y: (from.y as f32 + (to.y as f32 - from.y as f32) * t).round() as usize,
+			};
+			if pos == from || pos == to {
+				continue;
+			}
+
+			let tile = &self.tiles[self.index(pos).unwrap()];
+			let shot_height = from_shot_height + (to_shot_height - from_shot_height) * t;
+			let obstacle_height = (tile.height + tile.terrain.properties().sight_height) as f32;
+			if obstacle_height > shot_height {
+				return false;
+			}
+			if units_block && tile.occupant.is_some() && tile.height as f32 + UNIT_HEIGHT > shot_height {
+				return false;
+			}
+		}
+
+		true
+	}
+
+	// Put `entity` on the tile. Returns `false`, leaving the grid unchanged,
+	// if the tile is outside the grid or already occupied.
+	pub fn place(&mut self, pos: Pos, entity: Entity) -> bool {
+		match self.get_mut(pos) {
+			Some(tile) if tile.occupant.is_none() => {
+				tile.occupant = Some(entity);
+				true
+			},
+			_ => false,
+		}
+	}
+
+	pub fn remove_occupant(&mut self, pos: Pos) -> Option<Entity> {
+		self.get_mut(pos).and_then(|tile| tile.occupant.take())
+	}
+
+	// Move the occupant of `from` to `to`. Returns `false`, leaving the grid
+	// unchanged, if there's nothing to move or `to` is not free.
+	pub fn move_occupant(&mut self, from: Pos, to: Pos) -> bool {
+		if !self.in_bounds(to) || self.is_occupied(to) {
+			return false;
+		}
+		match self.remove_occupant(from) {
+			Some(entity) => self.place(to, entity),
+			None => false,
+		}
+	}
+
+	pub fn has_spawn_zone(&self, team: usize) -> bool {
+		self.tiles.iter().any(|tile| tile.flags.spawn_team == Some(team))
+	}
+}
+
+// A tile found by `BattleGrid::reachable_tiles`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ReachableTile {
+	// Movement points spent to reach the tile.
+	pub cost: usize,
+	// Tile the cheapest path comes from, `None` for the starting tile.
+	pub predecessor: Option<Pos>,
+}
+
+// The tiles a unit can move to, from `BattleGrid::reachable_tiles`.
+#[derive(Debug, Clone)]
+pub struct Reachability {
+	start: Pos,
+	tiles: HashMap<Pos, ReachableTile>,
+}
+
+impl Reachability {
+	// Whether the unit can move to `pos`. The tile the unit stands on is not
+	// a destination.
+	pub fn contains(&self, pos: Pos) -> bool {
+		pos != self.start && self.tiles.contains_key(&pos)
+	}
+
+	// The tiles the unit can move to, with the movement points spent to
+	// reach them.
+	pub fn destinations(&self) -> impl Iterator<Item = (Pos, usize)> + '_ {
+		self.tiles.iter()
+			.filter(|(pos, _)| **pos != self.start)
+			.map(|(pos, tile)| (*pos, tile.cost))
+	}
+
+	// The cheapest path to `destination`, from the starting tile to
+	// `destination`, both included.
+	pub fn path_to(&self, destination: Pos) -> Option<Vec<Pos>> {
+		if !self.contains(destination) {
+			return None;
+		}
+
+		let mut path = vec![destination];
+		while let Some(predecessor) = self.tiles[path.last().unwrap()].predecessor {
+			path.push(predecessor);
+		}
+		path.reverse();
+
+		Some(path)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	use crate::battle_map::MapCell;
+
+	// A flat grass grid, with the tiles in `walls` impassable.
+	fn grid(width: usize, height: usize, walls: &[Pos]) -> BattleGrid {
+		let cells = (0..width)
+			.map(|x| (0..height)
+				.map(|y| MapCell { height: 0, tile_type: TileType::Grass, passable: !walls.contains(&Pos { x, y }), spawn_team: None, })
+				.collect())
+			.collect();
+		BattleGrid::from_battle_map(&BattleMap { width, height, cells, })
+	}
+
+	// A single row of grass tiles of the given heights.
+	fn row(heights: &[usize]) -> BattleGrid {
+		let cells = heights.iter()
+			.map(|height| vec![MapCell { height: *height, tile_type: TileType::Grass, passable: true, spawn_team: None, }])
+			.collect();
+		BattleGrid::from_battle_map(&BattleMap { width: heights.len(), height: 1, cells, })
+	}
+
+	// A grid from rows of `(height, terrain)` tiles, the first row being
+	// y = 0.
+	fn terrain(rows: &[&[(usize, TileType)]]) -> BattleGrid {
+		let width = rows[0].len();
+		let cells = (0..width)
+			.map(|x| rows.iter()
+				.map(|row| MapCell { height: row[x].0, tile_type: row[x].1.clone(), passable: true, spawn_team: None, })
+				.collect())
+			.collect();
+		BattleGrid::from_battle_map(&BattleMap { width, height: rows.len(), cells, })
+	}
+
+	fn cost_to(reachability: &Reachability, destination: Pos) -> Option<usize> {
+		reachability.tiles.get(&destination).map(|tile| tile.cost)
+	}
+
+	#[test]
+	fn accessors_are_bounds_checked() {
+		let grid = grid(3, 2, &[]);
+
+		assert!(grid.in_bounds(Pos { x: 2, y: 1 }));
+		assert!(!grid.in_bounds(Pos { x: 3, y: 0 }));
+		assert!(!grid.in_bounds(Pos { x: 0, y: 2 }));
+		assert!(grid.get(Pos { x: 3, y: 0 }).is_none());
+		assert!(!grid.is_passable(Pos { x: 0, y: 2 }));
+	}
+
+	#[test]
+	fn neighbors_stay_inside_the_grid() {
+		let grid = grid(3, 3, &[]);
+
+		assert_eq!(grid.neighbors(Pos { x: 1, y: 1 }).count(), 4);
+		assert_eq!(grid.neighbors(Pos { x: 0, y: 0 }).collect::<Vec<Pos>>(), vec![Pos { x: 0, y: 1 }, Pos { x: 1, y: 0 }]);
+		assert_eq!(grid.neighbors(Pos { x: 2, y: 2 }).collect::<Vec<Pos>>(), vec![Pos { x: 2, y: 1 }, Pos { x: 1, y: 2 }]);
+		assert_eq!(grid.neighbors(Pos { x: 3, y: 0 }).count(), 0);
+	}
+
+	#[test]
+	fn units_walk_only_on_free_passable_tiles() {
+		let mut grid = grid(3, 1, &[Pos { x: 2, y: 0 }]);
+
+		assert!(grid.place(Pos { x: 0, y: 0 }, Entity::from_raw(1)));
+		assert!(!grid.place(Pos { x: 0, y: 0 }, Entity::from_raw(2)));
+		assert!(!grid.place(Pos { x: 3, y: 0 }, Entity::from_raw(2)));
+		assert!(!grid.is_walkable(Pos { x: 0, y: 0 }));
+		assert!(grid.is_walkable(Pos { x: 1, y: 0 }));
+		assert!(!grid.is_walkable(Pos { x: 2, y: 0 }));
+	}
+
+	#[test]
+	fn steps_only_up_and_down_to_jump_height() {
+		let mut grid = row(&[0, 2, 5, 4]);
+
+		assert!(!grid.can_step(Pos { x: 0, y: 0 }, Pos { x: 1, y: 0 }, 1));
+		assert!(grid.can_step(Pos { x: 0, y: 0 }, Pos { x: 1, y: 0 }, 2));
+		assert!(grid.can_step(Pos { x: 1, y: 0 }, Pos { x: 0, y: 0 }, 2));
+		assert!(!grid.can_step(Pos { x: 2, y: 0 }, Pos { x: 1, y: 0 }, 2));
+		assert!(!grid.can_step(Pos { x: 3, y: 0 }, Pos { x: 4, y: 0 }, 10));
+
+		grid.place(Pos { x: 3, y: 0 }, Entity::from_raw(1));
+		assert!(!grid.can_step(Pos { x: 2, y: 0 }, Pos { x: 3, y: 0 }, 1));
+	}
+
+	#[test]
+	fn terrain_sets_passability_and_movement_cost() {
+		let mut cells = vec![vec![MapCell { height: 0, tile_type: TileType::Grass, passable: true, spawn_team: None, }]; 3];
+		cells[1][0].tile_type = TileType::Forest;
+		cells[2][0].tile_type = TileType::Wall;
+		let grid = BattleGrid::from_battle_map(&BattleMap { width: 3, height: 1, cells, });
+
+		assert_eq!(grid.movement_cost(Pos { x: 0, y: 0 }), Some(1));
+		assert_eq!(grid.movement_cost(Pos { x: 1, y: 0 }), Some(2));
+		assert_eq!(grid.movement_cost(Pos { x: 3, y: 0 }), None);
+		assert!(grid.is_passable(Pos { x: 1, y: 0 }));
+		assert!(!grid.is_passable(Pos { x: 2, y: 0 }));
+	}
+
+	#[test]
+	fn moves_occupants_only_to_free_tiles() {
+		let mut grid = grid(3, 1, &[]);
+		grid.place(Pos { x: 0, y: 0 }, Entity::from_raw(1));
+		grid.place(Pos { x: 2, y: 0 }, Entity::from_raw(2));
+
+		assert!(!grid.move_occupant(Pos { x: 0, y: 0 }, Pos { x: 2, y: 0 }));
+		assert!(!grid.move_occupant(Pos { x: 0, y: 0 }, Pos { x: 3, y: 0 }));
+		assert!(!grid.move_occupant(Pos { x: 1, y: 0 }, Pos { x: 1, y: 0 }));
+		assert!(grid.move_occupant(Pos { x: 0, y: 0 }, Pos { x: 1, y: 0 }));
+		assert_eq!(grid.occupant(Pos { x: 1, y: 0 }), Some(Entity::from_raw(1)));
+		assert!(!grid.is_occupied(Pos { x: 0, y: 0 }));
+	}
+
+	#[test]
+	fn finds_spawn_zones() {
+		let mut cells = vec![vec![MapCell { height: 0, tile_type: TileType::Grass, passable: true, spawn_team: None, }]; 2];
+		cells[1][0].spawn_team = Some(2);
+		let grid = BattleGrid::from_battle_map(&BattleMap { width: 2, height: 1, cells, });
+
+		assert!(grid.has_spawn_zone(2));
+		assert!(!grid.has_spawn_zone(1));
+	}
+	#[test]
+	fn reaches_tiles_within_movement_range() {
+		let grid = grid(5, 5, &[]);
+		let reachability = grid.reachable_tiles(Pos { x: 2, y: 2 }, 2, 1);
+
+		assert!(reachability.contains(Pos { x: 4, y: 2 }));
+		assert!(reachability.contains(Pos { x: 3, y: 3 }));
+		assert!(!reachability.contains(Pos { x: 4, y: 3 }));
+		assert!(!reachability.contains(Pos { x: 2, y: 2 }));
+		assert_eq!(reachability.tiles.keys().filter(|pos| reachability.contains(**pos)).count(), 12);
+	}
+
+	#[test]
+	fn takes_the_cheapest_path_through_terrain() {
+		use TileType::*;
+
+		let grid = terrain(&[
+			&[(0, Grass), (0, Water), (0, Grass)],
+			&[(0, Grass), (0, Forest), (0, Grass)],
+			&[(0, Grass), (0, Grass), (0, Grass)],
+		]);
+		let reachability = grid.reachable_tiles(Pos { x: 0, y: 0 }, 4, 1);
+
+		assert_eq!(cost_to(&reachability, Pos { x: 1, y: 0 }), Some(3));
+		assert_eq!(cost_to(&reachability, Pos { x: 2, y: 0 }), Some(4));
+		assert_eq!(reachability.path_to(Pos { x: 2, y: 1 }).unwrap(), vec![
+			Pos { x: 0, y: 0 },
+			Pos { x: 0, y: 1 },
+			Pos { x: 1, y: 1 },
+			Pos { x: 2, y: 1 },
+		]);
+		assert_eq!(reachability.path_to(Pos { x: 0, y: 0 }), None);
+		assert_eq!(reachability.path_to(Pos { x: 3, y: 0 }), None);
+	}
+
+	#[test]
+	fn does_not_climb_higher_than_jump() {
+		let grid = row(&[0, 2, 4]);
+
+		assert!(!grid.reachable_tiles(Pos { x: 0, y: 0 }, 5, 1).contains(Pos { x: 1, y: 0 }));
+		assert!(grid.reachable_tiles(Pos { x: 0, y: 0 }, 5, 2).contains(Pos { x: 2, y: 0 }));
+		assert!(grid.reachable_tiles(Pos { x: 2, y: 0 }, 5, 2).contains(Pos { x: 0, y: 0 }));
+	}
+
+	#[test]
+	fn does_not_walk_through_walls_or_units() {
+		use TileType::*;
+
+		let mut grid = terrain(&[&[(0, Grass), (0, Wall), (0, Grass)], &[(0, Grass), (0, Grass), (0, Grass)]]);
+		grid.place(Pos { x: 1, y: 1 }, Entity::from_raw(1));
+		let reachability = grid.reachable_tiles(Pos { x: 0, y: 0 }, 10, 1);
+
+		assert!(reachability.contains(Pos { x: 0, y: 1 }));
+		assert!(!reachability.contains(Pos { x: 1, y: 0 }));
+		assert!(!reachability.contains(Pos { x: 1, y: 1 }));
+		assert!(!reachability.contains(Pos { x: 2, y: 0 }));
+	}
+	#[test]
+	fn walls_and_forests_block_line_of_sight() {
+		use TileType::*;
+
+		let grid = terrain(&[&[(0, Grass), (0, Wall), (0, Grass), (1, Forest), (0, Grass)]]);
+
+		assert!(!grid.has_line_of_sight(Pos { x: 0, y: 0 }, Pos { x: 2, y: 0 }, false));
+		assert!(!grid.has_line_of_sight(Pos { x: 2, y: 0 }, Pos { x: 4, y: 0 }, false));
+		assert!(grid.has_line_of_sight(Pos { x: 2, y: 0 }, Pos { x: 3, y: 0 }, false));
+	}
+
+	#[test]
+	fn higher_ground_sees_over_obstacles() {
+		use TileType::*;
+
+		let low_grid = terrain(&[&[(0, Grass), (1, Forest), (0, Grass)]]);
+		let high_grid = terrain(&[&[(4, Grass), (1, Forest), (0, Grass)]]);
+
+		assert!(!low_grid.has_line_of_sight(Pos { x: 0, y: 0 }, Pos { x: 2, y: 0 }, false));
+		assert!(high_grid.has_line_of_sight(Pos { x: 0, y: 0 }, Pos { x: 2, y: 0 }, false));
+	}
+
+	#[test]
+	fn units_block_line_of_sight_only_when_enabled() {
+		let mut grid = grid(3, 1, &[]);
+		grid.place(Pos { x: 1, y: 0 }, Entity::from_raw(1));
+
+		assert!(grid.has_line_of_sight(Pos { x: 0, y: 0 }, Pos { x: 2, y: 0 }, false));
+		assert!(!grid.has_line_of_sight(Pos { x: 0, y: 0 }, Pos { x: 2, y: 0 }, true));
+	}
+
+	#[test]
+	fn sees_across_the_whole_grid_diagonally() {
+		let grid = grid(4, 4, &[]);
+
+		assert!(grid.has_line_of_sight(Pos { x: 0, y: 0 }, Pos { x: 3, y: 3 }, true));
+		assert!(grid.has_line_of_sight(Pos { x: 3, y: 0 }, Pos { x: 0, y: 3 }, true));
+		assert!(!grid.has_line_of_sight(Pos { x: 0, y: 0 }, Pos { x: 4, y: 4 }, true));
+	}
+}
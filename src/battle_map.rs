@@ -0,0 +1,270 @@
+// (C) Copyright 2023 Ars Militaris Dev
+
+// Battle map files.
+//
+// A map file describes the battlefield of a battle, one row of tiles per
+// line. The first row is y = 0, and the first tile of each row is x = 0.
+// Empty lines and lines starting with `#` are ignored.
+//
+// Tiles are separated by whitespace. Each tile is written as its height,
+// followed by a terrain letter and optional flags:
+//
+//   1G     Grass at height 1.
+//   3G#    Impassable grass at height 3.
+//   1G@2   Grass at height 1, in the spawn zone of team 2.
+//
+// Terrain letters:
+//
+//   G      Grass
+//   R      Road
+//   F      Forest
+//   W      Water
+//   X      Wall (always impassable)
+//
+// Movement costs and combat bonuses of each terrain are defined in the
+// terrain table, in `terrain.rs`.
+
+use crate::TileType;
+
+use std::fmt;
+use std::fs;
+
+// A tile of a battle map, as described in the map file.
+#[derive(Debug, Clone)]
+pub struct MapCell {
+	pub height: usize,
+	pub tile_type: TileType,
+	pub passable: bool,
+	pub spawn_team: Option<usize>,
+}
+
+// A battle map, indexed as `cells[x][y]`.
+#[derive(Debug, Clone)]
+pub struct BattleMap {
+	pub width: usize,
+	pub height: usize,
+	pub cells: Vec<Vec<MapCell>>,
+}
+
+#[derive(Debug)]
+pub enum MapErrorKind {
+	Io(std::io::Error),
+	Empty,
+	MissingHeight,
+	InvalidHeight(String),
+	MissingTerrain,
+	UnknownTerrain(char),
+	InvalidSpawnTeam(String),
+	UnexpectedCharacter(char),
+	RowLength { expected: usize, found: usize },
+}
+
+#[derive(Debug)]
+pub struct MapError {
+	pub path: String,
+	// Line and column (1-based) of the error, when it comes from a tile.
+	pub location: Option<(usize, usize)>,
+	pub kind: MapErrorKind,
+}
+
+impl fmt::Display for MapError {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		write!(f, "{}", self.path)?;
+		if let Some((line, column)) = self.location {
+			write!(f, ":{}:{}", line, column)?;
+		}
+		match &self.kind {
+			MapErrorKind::Io(error) => write!(f, ": could not read map file: {}", error),
+			MapErrorKind::Empty => write!(f, ": map has no tiles"),
+			MapErrorKind::MissingHeight => write!(f, ": tile has no height"),
+			MapErrorKind::InvalidHeight(height) => write!(f, ": invalid tile height {:?}", height),
+			MapErrorKind::MissingTerrain => write!(f, ": tile has no terrain"),
+			MapErrorKind::UnknownTerrain(terrain) => write!(f, ": unknown terrain {:?}", terrain),
+			MapErrorKind::InvalidSpawnTeam(team) => write!(f, ": invalid spawn team {:?}", team),
+			MapErrorKind::UnexpectedCharacter(character) => write!(f, ": unexpected character {:?}", character),
+			MapErrorKind::RowLength { expected, found } => write!(f, ": row has {} tiles, expected {}", found, expected),
+		}
+	}
+}
+
+impl std::error::Error for MapError {}
+
+impl TileType {
+	fn from_letter(letter: char) -> Option<TileType> {
+		match letter {
+			'G' => Some(TileType::Grass),
+			'R' => Some(TileType::Road),
+			'F' => Some(TileType::Forest),
+			'W' => Some(TileType::Water),
+			'X' => Some(TileType::Wall),
+			_ => None,
+		}
+	}
+}
+
+impl BattleMap {
+	pub fn from_file(path: &str) -> Result<BattleMap, MapError> {
+		let contents = fs::read_to_string(path).map_err(|error| MapError {
+			path: path.to_string(),
+			location: None,
+			kind: MapErrorKind::Io(error),
+		})?;
+
+		BattleMap::parse(&contents).map_err(|(location, kind)| MapError {
+			path: path.to_string(),
+			location,
+			kind,
+		})
+	}
+
+	fn parse(contents: &str) -> Result<BattleMap, (Option<(usize, usize)>, MapErrorKind)> {
+		// Read the rows, as `rows[y][x]`.
+		let mut rows: Vec<Vec<MapCell>> = Vec::new();
+		for (line_index, line) in contents.lines().enumerate() {
+			let trimmed = line.trim();
+			if trimmed.is_empty() || trimmed.starts_with('#') {
+				continue;
+			}
+
+			let mut row = Vec::new();
+			let mut column = 0;
+			for token in line.split_whitespace() {
+				// Find the column of the token for error reporting.
+				column = line[column..].find(token).unwrap() + column;
+				let location = Some((line_index + 1, column + 1));
+
+				row.push(parse_cell(token).map_err(|kind| (location, kind))?);
+				column += token.len();
+			}
+
+			if let Some(first_row) = rows.first() {
+				if row.len() != first_row.len() {
+					return Err((Some((line_index + 1, 1)), MapErrorKind::RowLength { expected: first_row.len(), found: row.len() }));
+				}
+			}
+			rows.push(row);
+		}
+
+		if rows.is_empty() {
+			return Err((None, MapErrorKind::Empty));
+		}
+
+		// Transpose the rows to index the map as `cells[x][y]`, like the rest
+		// of amserver does.
+		let width = rows[0].len();
+		let height = rows.len();
+		let mut cells: Vec<Vec<MapCell>> = (0..width).map(|_| Vec::with_capacity(height)).collect();
+		for row in rows {
+			for (x, cell) in row.into_iter().enumerate() {
+				cells[x].push(cell);
+			}
+		}
+
+		Ok(BattleMap { width, height, cells })
+	}
+}
+
+fn parse_cell(token: &str) -> Result<MapCell, MapErrorKind> {
+	// Height.
+	let digits_end = token.find(|character: char| !character.is_ascii_digit()).unwrap_or(token.len());
+	if digits_end == 0 {
+		return Err(MapErrorKind::MissingHeight);
+	}
+	let height = token[..digits_end].parse().map_err(|_| MapErrorKind::InvalidHeight(token[..digits_end].to_string()))?;
+
+	// Terrain.
+	let mut rest = token[digits_end..].chars();
+	let tile_type = match rest.next() {
+		Some(letter) => TileType::from_letter(letter).ok_or(MapErrorKind::UnknownTerrain(letter))?,
+		None => return Err(MapErrorKind::MissingTerrain),
+	};
+
+	// Flags.
+	let mut cell = MapCell {
+		height,
+		tile_type,
+		passable: true,
+		spawn_team: None,
+	};
+	let flags = rest.as_str();
+	let mut index = 0;
+	while index < flags.len() {
+		match flags[index..].chars().next().unwrap() {
+			'#' => {
+				cell.passable = false;
+				index += 1;
+			},
+			'@' => {
+				let team = &flags[index + 1..];
+				let team_end = team.find(|character: char| !character.is_ascii_digit()).unwrap_or(team.len());
+				cell.spawn_team = Some(team[..team_end].parse().map_err(|_| MapErrorKind::InvalidSpawnTeam(team.to_string()))?);
+				index += 1 + team_end;
+			},
+			character => return Err(MapErrorKind::UnexpectedCharacter(character)),
+		}
+	}
+
+	Ok(cell)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn parses_tiles_as_columns() {
+		let map = BattleMap::parse("# A small map.\n1G 2R\n\n3F# 0W@2\n").unwrap();
+
+		assert_eq!((map.width, map.height), (2, 2));
+		assert_eq!(map.cells[0][0].height, 1);
+		assert_eq!(map.cells[1][0].height, 2);
+		assert_eq!(map.cells[1][0].tile_type, TileType::Road);
+		assert_eq!(map.cells[0][1].height, 3);
+		assert_eq!(map.cells[0][1].tile_type, TileType::Forest);
+		assert_eq!(map.cells[1][1].tile_type, TileType::Water);
+		assert!(!map.cells[0][1].passable);
+		assert_eq!(map.cells[1][1].spawn_team, Some(2));
+		assert!(map.cells[1][1].passable);
+	}
+
+	#[test]
+	fn parses_flags_in_any_order() {
+		let cell = parse_cell("2G@1#").unwrap();
+
+		assert_eq!(cell.spawn_team, Some(1));
+		assert!(!cell.passable);
+	}
+
+	#[test]
+	fn reports_invalid_tiles_with_their_location() {
+		let (location, kind) = BattleMap::parse("1G 1G\n1G 1Q\n").unwrap_err();
+
+		assert_eq!(location, Some((2, 4)));
+		assert!(matches!(kind, MapErrorKind::UnknownTerrain('Q')));
+	}
+
+	#[test]
+	fn rejects_invalid_tiles() {
+		assert!(matches!(parse_cell("G"), Err(MapErrorKind::MissingHeight)));
+		assert!(matches!(parse_cell("1"), Err(MapErrorKind::MissingTerrain)));
+		assert!(matches!(parse_cell("1G@"), Err(MapErrorKind::InvalidSpawnTeam(_))));
+		assert!(matches!(parse_cell("1G!"), Err(MapErrorKind::UnexpectedCharacter('!'))));
+		assert!(matches!(parse_cell("99999999999999999999999G"), Err(MapErrorKind::InvalidHeight(_))));
+	}
+
+	#[test]
+	fn rejects_rows_of_different_lengths() {
+		let (location, kind) = BattleMap::parse("1G 1G\n1G\n").unwrap_err();
+
+		assert_eq!(location, Some((2, 1)));
+		assert!(matches!(kind, MapErrorKind::RowLength { expected: 2, found: 1 }));
+	}
+
+	#[test]
+	fn rejects_maps_without_tiles() {
+		let (location, kind) = BattleMap::parse("# Nothing here.\n\n").unwrap_err();
+
+		assert_eq!(location, None);
+		assert!(matches!(kind, MapErrorKind::Empty));
+	}
+}
@@ -0,0 +1,459 @@
+// (C) Copyright 2023 Ars Militaris Dev
+
+use rand::Rng;
+
+use serde::{Deserialize, Serialize};
+
+// Maximum random deviation, in HP, applied to the damage of an attack.
+const DAMAGE_SPREAD: isize = 3;
+
+// Chance to hit, in percent, between units with the same DEX and AGI.
+const BASE_HIT_CHANCE: usize = 80;
+
+// Bounds of the chance to hit, so that no attack is ever certain to land or
+// to miss.
+const MIN_HIT_CHANCE: usize = 5;
+const MAX_HIT_CHANCE: usize = 99;
+
+// Highest chance of a critical hit, in percent.
+const MAX_CRITICAL_CHANCE: usize = 50;
+
+// Percentage of the damage dealt by a critical hit.
+const CRITICAL_DAMAGE_PERCENT: usize = 150;
+
+// Attacker stats that take part in a basic attack.
+pub struct AttackStats {
+	pub str: usize,
+	pub dex: usize,
+	pub luk: usize,
+}
+
+// Defender stats that take part in a basic attack, including the bonuses of
+// the terrain the defender stands on and of its status effects, as
+// percentages.
+pub struct DefenseStats {
+	pub vit: usize,
+	pub agi: usize,
+	pub luk: usize,
+	pub terrain_defense: usize,
+	pub terrain_evasion: usize,
+	pub status_defense: usize,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub enum AttackResult {
+	Hit,
+	Critical,
+	Miss,
+}
+
+// Side of the defender an attack comes from, given the way it is facing.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub enum AttackAngle {
+	Front,
+	Side,
+	Back,
+}
+
+// Circumstances of a strike that change its chance to hit and its damage.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct StrikeModifiers {
+	// Percentage taken off the defender's chance of avoiding the strike.
+	pub hit_bonus: usize,
+	// Percentage of the damage dealt, 100 for a normal strike.
+	pub damage_percent: usize,
+}
+
+impl AttackAngle {
+	// Attacks from the side, and even more from behind, are harder to avoid
+	// and hurt more.
+	pub fn modifiers(self) -> StrikeModifiers {
+		match self {
+			AttackAngle::Front => StrikeModifiers { hit_bonus: 0, damage_percent: 100, },
+			AttackAngle::Side => StrikeModifiers { hit_bonus: 10, damage_percent: 115, },
+			AttackAngle::Back => StrikeModifiers { hit_bonus: 25, damage_percent: 130, },
+		}
+	}
+
+	// Percentage of the damage of a counterattack against an attack from this
+	// angle. Units attacked from behind are caught off guard and don't strike
+	// back at all.
+	pub fn counter_damage_percent(self) -> usize {
+		match self {
+			AttackAngle::Front => 100,
+			AttackAngle::Side => 75,
+			AttackAngle::Back => 0,
+		}
+	}
+}
+
+// Authoritative result of an attack, as sent to the clients.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy)]
+pub struct AttackOutcome {
+	pub result: AttackResult,
+	pub damage: usize,
+	pub target_hp: usize,
+}
+
+// Damage of an attack before the random spread is applied.
+//
+// STR is the main source of damage, with DEX and LUK adding a little on top.
+// VIT absorbs most of it, and AGI lets the defender avoid part of the blow.
+// An attack always does at least 1 damage.
+pub fn base_damage(attack: &AttackStats, defense: &DefenseStats) -> usize {
+	let attack_power = attack.str / 2 + attack.dex / 8 + attack.luk / 16;
+	let defense_power = defense.vit / 4 + defense.agi / 8;
+
+	attack_power.saturating_sub(defense_power).max(1)
+}
+
+// Damage of an attack, including a random spread of +/- `DAMAGE_SPREAD`,
+// reduced by the defense bonuses of the defender's terrain and status effects.
+pub fn roll_damage(attack: &AttackStats, defense: &DefenseStats, rng: &mut impl Rng) -> usize {
+	let modifier = rng.gen_range(-DAMAGE_SPREAD..=DAMAGE_SPREAD);
+	let damage = (base_damage(attack, defense) as isize + modifier).max(1) as usize;
+
+	(damage * 100usize.saturating_sub(defense.terrain_defense + defense.status_defense) / 100).max(1)
+}
+
+// Chance, in percent, that an attack lands.
+//
+// DEX makes the attacker more accurate and AGI lets the defender dodge. The
+// evasion bonus of the defender's terrain is taken off, and the hit bonus of
+// the strike is added.
+pub fn hit_chance(attack: &AttackStats, defense: &DefenseStats, modifiers: StrikeModifiers) -> usize {
+	let chance = BASE_HIT_CHANCE as isize
+		+ attack.dex as isize / 4
+		- defense.agi as isize / 4
+		- defense.terrain_evasion as isize
+		+ modifiers.hit_bonus as isize;
+
+	chance.clamp(MIN_HIT_CHANCE as isize, MAX_HIT_CHANCE as isize) as usize
+}
+
+// Chance, in percent, that an attack that lands is a critical hit. The
+// attacker's LUK makes critical hits more likely, the defender's LUK less.
+pub fn critical_chance(attack: &AttackStats, defense: &DefenseStats) -> usize {
+	(attack.luk / 5).saturating_sub(defense.luk / 10).min(MAX_CRITICAL_CHANCE)
+}
+
+// Resolve a basic attack against a target that has `target_hp` HP left.
+//
+// The attack first has to land, then may be a critical hit, which does
+// `CRITICAL_DAMAGE_PERCENT` of the normal damage.
+pub fn resolve_basic_attack(attack: &AttackStats, defense: &DefenseStats, modifiers: StrikeModifiers, target_hp: usize, rng: &mut impl Rng) -> AttackOutcome {
+	if rng.gen_range(0..100) >= hit_chance(attack, defense, modifiers) {
+		return AttackOutcome {
+			result: AttackResult::Miss,
+			damage: 0,
+			target_hp,
+		};
+	}
+
+	let mut result = AttackResult::Hit;
+	let mut damage = roll_damage(attack, defense, rng) * modifiers.damage_percent / 100;
+	if rng.gen_range(0..100) < critical_chance(attack, defense) {
+		result = AttackResult::Critical;
+		damage = damage * CRITICAL_DAMAGE_PERCENT / 100;
+	}
+	let damage = damage.max(1);
+
+	AttackOutcome {
+		result,
+		damage,
+		target_hp: target_hp.saturating_sub(damage),
+	}
+}
+
+// AGI advantage a unit needs over its opponent to strike a second time in
+// a combat exchange.
+pub const FOLLOW_UP_SPEED: usize = 20;
+
+// A unit taking part in a combat exchange.
+pub struct Combatant {
+	pub attack: AttackStats,
+	pub defense: DefenseStats,
+	pub hp: usize,
+	pub speed: usize,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Side {
+	Attacker,
+	Defender,
+}
+
+// One strike of a combat exchange.
+#[derive(Debug, Clone, Copy)]
+pub struct Strike {
+	pub striker: Side,
+	pub is_counterattack: bool,
+	pub angle: AttackAngle,
+	pub outcome: AttackOutcome,
+}
+
+// Resolve a full combat exchange between `attacker` and `defender`, with the
+// attacker coming from `angle`.
+//
+// The attacker strikes first. If the defender survives and `can_counter` is
+// set, it strikes back once, unless it was attacked from behind. Then, if
+// both are still standing, the unit that is at least `FOLLOW_UP_SPEED` faster
+// than the other strikes a second time (the defender only if it could
+// counterattack). The exchange stops as soon as a unit falls, and never has
+// more than three strikes, so it always ends.
+//
+// A defender that strikes back turns to face the attacker, so the attacker's
+// follow-up strike after a counterattack always comes from the front.
+pub fn resolve_exchange(attacker: &Combatant, defender: &Combatant, angle: AttackAngle, can_counter: bool, rng: &mut impl Rng) -> Vec<Strike> {
+	let mut strikes = Vec::new();
+	let mut attacker_hp = attacker.hp;
+	let mut defender_hp = defender.hp;
+	let mut attacker_angle = angle;
+
+	let can_counter = can_counter && angle.counter_damage_percent() > 0;
+
+	let mut order = vec![Side::Attacker];
+	if can_counter {
+		order.push(Side::Defender);
+	}
+	if attacker.speed >= defender.speed + FOLLOW_UP_SPEED {
+		order.push(Side::Attacker);
+	} else if can_counter && defender.speed >= attacker.speed + FOLLOW_UP_SPEED {
+		order.push(Side::Defender);
+	}
+
+	for striker in order {
+		if attacker_hp == 0 || defender_hp == 0 {
+			break;
+		}
+
+		let (strike_angle, outcome) = match striker {
+			Side::Attacker => {
+				let outcome = resolve_basic_attack(&attacker.attack, &defender.defense, attacker_angle.modifiers(), defender_hp, rng);
+				defender_hp = outcome.target_hp;
+				(attacker_angle, outcome)
+			},
+			Side::Defender => {
+				let modifiers = StrikeModifiers { hit_bonus: 0, damage_percent: angle.counter_damage_percent(), };
+				let outcome = resolve_basic_attack(&defender.attack, &attacker.defense, modifiers, attacker_hp, rng);
+				attacker_hp = outcome.target_hp;
+				attacker_angle = AttackAngle::Front;
+				(AttackAngle::Front, outcome)
+			},
+		};
+
+		strikes.push(Strike {
+			striker,
+			is_counterattack: striker == Side::Defender,
+			angle: strike_angle,
+			outcome,
+		});
+	}
+
+	strikes
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	use rand::rngs::StdRng;
+	use rand::SeedableRng;
+
+	const ATTACK: AttackStats = AttackStats { str: 60, dex: 60, luk: 50, };
+	const FRONT: StrikeModifiers = StrikeModifiers { hit_bonus: 0, damage_percent: 100, };
+	const DEFENSE: DefenseStats = DefenseStats { vit: 60, agi: 60, luk: 50, terrain_defense: 0, terrain_evasion: 0, status_defense: 0, };
+
+	#[test]
+	fn strength_against_vitality_sets_the_damage() {
+		assert_eq!(base_damage(&ATTACK, &DEFENSE), 18);
+		assert_eq!(base_damage(&AttackStats { str: 100, ..ATTACK }, &DEFENSE), 38);
+		assert_eq!(base_damage(&ATTACK, &DefenseStats { vit: 100, ..DEFENSE }), 8);
+	}
+
+	#[test]
+	fn attacks_always_do_some_damage() {
+		let attack = AttackStats { str: 0, dex: 0, luk: 0, };
+		let defense = DefenseStats { vit: 200, agi: 200, ..DEFENSE };
+		let mut rng = StdRng::seed_from_u64(0);
+
+		assert_eq!(base_damage(&attack, &defense), 1);
+		for _ in 0..100 {
+			assert!(roll_damage(&attack, &defense, &mut rng) >= 1);
+		}
+	}
+
+	#[test]
+	fn damage_spreads_around_the_base_damage() {
+		let mut rng = StdRng::seed_from_u64(0);
+		let rolls: Vec<usize> = (0..1000).map(|_| roll_damage(&ATTACK, &DEFENSE, &mut rng)).collect();
+
+		assert!(rolls.iter().all(|damage| damage.abs_diff(18) <= DAMAGE_SPREAD as usize));
+		assert!(rolls.contains(&15) && rolls.contains(&21));
+	}
+
+	#[test]
+	fn targets_lose_the_damage_dealt() {
+		let mut rng = StdRng::seed_from_u64(0);
+
+		let outcome = resolve_basic_attack(&ATTACK, &DEFENSE, FRONT, 100, &mut rng);
+		assert_ne!(outcome.result, AttackResult::Miss);
+		assert_eq!(outcome.target_hp, 100 - outcome.damage);
+
+		let outcome = resolve_basic_attack(&ATTACK, &DEFENSE, FRONT, 5, &mut rng);
+		assert_eq!(outcome.target_hp, 0);
+	}
+
+	#[test]
+	fn terrain_absorbs_damage() {
+		let mut rng = StdRng::seed_from_u64(0);
+		let defense = DefenseStats { terrain_defense: 50, ..DEFENSE };
+
+		for _ in 0..100 {
+			assert!(roll_damage(&ATTACK, &defense, &mut rng) <= (18 + DAMAGE_SPREAD as usize) / 2);
+		}
+		assert_eq!(roll_damage(&ATTACK, &DefenseStats { terrain_defense: 100, ..DEFENSE }, &mut rng), 1);
+		assert_eq!(roll_damage(&ATTACK, &DefenseStats { terrain_defense: 50, status_defense: 50, ..DEFENSE }, &mut rng), 1);
+	}
+
+	#[test]
+	fn terrain_evasion_makes_attacks_miss() {
+		let mut rng = StdRng::seed_from_u64(0);
+
+		let outcome = resolve_basic_attack(&ATTACK, &DefenseStats { terrain_evasion: 100, ..DEFENSE }, FRONT, 100, &mut rng);
+		assert_eq!(outcome.result, AttackResult::Miss);
+		assert_eq!((outcome.damage, outcome.target_hp), (0, 100));
+	}
+
+	#[test]
+	fn dexterity_and_agility_set_the_chance_to_hit() {
+		assert_eq!(hit_chance(&ATTACK, &DEFENSE, FRONT), BASE_HIT_CHANCE);
+		assert_eq!(hit_chance(&AttackStats { dex: 80, ..ATTACK }, &DEFENSE, FRONT), BASE_HIT_CHANCE + 5);
+		assert_eq!(hit_chance(&ATTACK, &DefenseStats { agi: 80, ..DEFENSE }, FRONT), BASE_HIT_CHANCE - 5);
+		assert_eq!(hit_chance(&ATTACK, &DefenseStats { terrain_evasion: 20, ..DEFENSE }, AttackAngle::Side.modifiers()), BASE_HIT_CHANCE - 10);
+	}
+
+	#[test]
+	fn attacks_are_never_certain_to_hit_or_miss() {
+		assert_eq!(hit_chance(&AttackStats { dex: 500, ..ATTACK }, &DEFENSE, FRONT), MAX_HIT_CHANCE);
+		assert_eq!(hit_chance(&ATTACK, &DefenseStats { agi: 500, ..DEFENSE }, FRONT), MIN_HIT_CHANCE);
+	}
+
+	#[test]
+	fn luck_sets_the_chance_of_critical_hits() {
+		assert_eq!(critical_chance(&ATTACK, &DEFENSE), 5);
+		assert_eq!(critical_chance(&AttackStats { luk: 0, ..ATTACK }, &DEFENSE), 0);
+		assert_eq!(critical_chance(&ATTACK, &DefenseStats { luk: 200, ..DEFENSE }), 0);
+		assert_eq!(critical_chance(&AttackStats { luk: 1000, ..ATTACK }, &DEFENSE), MAX_CRITICAL_CHANCE);
+	}
+
+	#[test]
+	fn critical_hits_do_more_damage() {
+		let mut rng = StdRng::seed_from_u64(0);
+		let attack = AttackStats { luk: 1000, ..ATTACK };
+		let outcomes: Vec<AttackOutcome> = (0..1000).map(|_| resolve_basic_attack(&attack, &DEFENSE, FRONT, 1000, &mut rng)).collect();
+
+		let critical_hits: Vec<&AttackOutcome> = outcomes.iter().filter(|outcome| outcome.result == AttackResult::Critical).collect();
+		assert!(!critical_hits.is_empty());
+		assert!(critical_hits.iter().all(|outcome| outcome.damage >= (18 - DAMAGE_SPREAD as usize) * CRITICAL_DAMAGE_PERCENT / 100));
+		assert!(outcomes.iter().any(|outcome| outcome.result == AttackResult::Hit));
+	}
+
+	fn combatant(hp: usize, speed: usize) -> Combatant {
+		Combatant {
+			attack: AttackStats { str: 60, dex: 60, luk: 50, },
+			defense: DefenseStats { agi: speed, ..DEFENSE },
+			hp,
+			speed,
+		}
+	}
+
+	fn resolve_exchange_from_front(attacker: &Combatant, defender: &Combatant, can_counter: bool, rng: &mut StdRng) -> Vec<Strike> {
+		resolve_exchange(attacker, defender, AttackAngle::Front, can_counter, rng)
+	}
+
+	fn strikers(strikes: &[Strike]) -> Vec<Side> {
+		strikes.iter().map(|strike| strike.striker).collect()
+	}
+
+	#[test]
+	fn defender_strikes_back_once() {
+		let mut rng = StdRng::seed_from_u64(0);
+		let strikes = resolve_exchange_from_front(&combatant(1000, 60), &combatant(1000, 60), true, &mut rng);
+
+		assert_eq!(strikers(&strikes), vec![Side::Attacker, Side::Defender]);
+		assert!(!strikes[0].is_counterattack);
+		assert!(strikes[1].is_counterattack);
+	}
+
+	#[test]
+	fn no_counterattack_when_not_allowed() {
+		let mut rng = StdRng::seed_from_u64(0);
+		let strikes = resolve_exchange_from_front(&combatant(1000, 60), &combatant(1000, 60), false, &mut rng);
+
+		assert_eq!(strikers(&strikes), vec![Side::Attacker]);
+	}
+
+	#[test]
+	fn faster_units_strike_twice() {
+		let mut rng = StdRng::seed_from_u64(0);
+
+		let strikes = resolve_exchange_from_front(&combatant(1000, 60 + FOLLOW_UP_SPEED), &combatant(1000, 60), true, &mut rng);
+		assert_eq!(strikers(&strikes), vec![Side::Attacker, Side::Defender, Side::Attacker]);
+
+		let strikes = resolve_exchange_from_front(&combatant(1000, 60), &combatant(1000, 60 + FOLLOW_UP_SPEED), true, &mut rng);
+		assert_eq!(strikers(&strikes), vec![Side::Attacker, Side::Defender, Side::Defender]);
+
+		// A defender that can't strike back doesn't get a follow-up either.
+		let strikes = resolve_exchange_from_front(&combatant(1000, 60), &combatant(1000, 60 + FOLLOW_UP_SPEED), false, &mut rng);
+		assert_eq!(strikers(&strikes), vec![Side::Attacker]);
+	}
+
+	#[test]
+	fn exchange_stops_when_a_unit_falls() {
+		let mut rng = StdRng::seed_from_u64(0);
+		let strikes = resolve_exchange_from_front(&combatant(1000, 60 + FOLLOW_UP_SPEED), &combatant(1, 60), true, &mut rng);
+
+		assert_eq!(strikers(&strikes), vec![Side::Attacker]);
+		assert_eq!(strikes[0].outcome.target_hp, 0);
+	}
+
+	#[test]
+	fn flank_and_back_attacks_hit_harder() {
+		let defense = DefenseStats { terrain_evasion: 20, ..DEFENSE };
+		let mut rng = StdRng::seed_from_u64(0);
+
+		let hits = |modifiers: StrikeModifiers, rng: &mut StdRng| (0..1000)
+			.map(|_| resolve_basic_attack(&ATTACK, &defense, modifiers, 1000, rng))
+			.filter(|outcome| outcome.result != AttackResult::Miss)
+			.count();
+		let front_hits = hits(AttackAngle::Front.modifiers(), &mut rng);
+		let back_hits = hits(AttackAngle::Back.modifiers(), &mut rng);
+		assert!(front_hits < back_hits);
+
+		let damage = |modifiers: StrikeModifiers| resolve_basic_attack(&ATTACK, &DEFENSE, modifiers, 1000, &mut StdRng::seed_from_u64(0)).damage;
+		assert!(damage(AttackAngle::Front.modifiers()) < damage(AttackAngle::Side.modifiers()));
+		assert!(damage(AttackAngle::Side.modifiers()) < damage(AttackAngle::Back.modifiers()));
+	}
+
+	#[test]
+	fn units_attacked_from_behind_do_not_strike_back() {
+		let mut rng = StdRng::seed_from_u64(0);
+
+		let strikes = resolve_exchange(&combatant(1000, 60), &combatant(1000, 60), AttackAngle::Back, true, &mut rng);
+		assert_eq!(strikers(&strikes), vec![Side::Attacker]);
+		assert_eq!(strikes[0].angle, AttackAngle::Back);
+
+		let strikes = resolve_exchange(&combatant(1000, 60), &combatant(1000, 60), AttackAngle::Side, true, &mut rng);
+		assert_eq!(strikers(&strikes), vec![Side::Attacker, Side::Defender]);
+	}
+
+	#[test]
+	fn follow_ups_after_a_counterattack_come_from_the_front() {
+		let mut rng = StdRng::seed_from_u64(0);
+		let strikes = resolve_exchange(&combatant(1000, 60 + FOLLOW_UP_SPEED), &combatant(1000, 60), AttackAngle::Side, true, &mut rng);
+
+		let angles: Vec<AttackAngle> = strikes.iter().map(|strike| strike.angle).collect();
+		assert_eq!(angles, vec![AttackAngle::Side, AttackAngle::Front, AttackAngle::Front]);
+	}
+}
@@ -0,0 +1,463 @@
+// (C) Copyright 2023 Ars Militaris Dev
+
+use bevy::prelude::*;
+
+use bevy_quinnet::server::{certificate::CertificateRetrievalMode, ServerConfiguration};
+
+use serde::Deserialize;
+
+use std::env;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::net::{IpAddr, Ipv4Addr};
+
+// Name of the scenario made of the `map_file` and `units_file` of the battle
+// configuration, which the lobby starts with.
+pub const DEFAULT_SCENARIO: &str = "default";
+
+// Path of the configuration file read when neither `--config` nor
+// `AMSERVER_CONFIG` are given. It is optional: if it doesn't exist, the
+// defaults are used.
+pub const DEFAULT_CONFIG_PATH: &str = "amserver.toml";
+
+pub const USAGE: &str = "\
+Usage: amserver [OPTIONS]
+
+Options:
+  --config <PATH>          Configuration file (env: AMSERVER_CONFIG, default: amserver.toml)
+  --bind-address <IP>      Address to listen on (env: AMSERVER_BIND_ADDRESS)
+  --port <PORT>            Port to listen on (env: AMSERVER_PORT)
+  --cert-file <PATH>       Load the certificate from a file (env: AMSERVER_CERT_FILE)
+  --key-file <PATH>        Load the certificate key from a file (env: AMSERVER_KEY_FILE)
+  --kafka-hosts <HOSTS>    Comma separated Kafka brokers (env: AMSERVER_KAFKA_HOSTS)
+  --kafka-consumer-topic <TOPIC>
+                           Kafka topic to consume (env: AMSERVER_KAFKA_CONSUMER_TOPIC)
+  --kafka-producer-topic <TOPIC>
+                           Kafka topic to produce to (env: AMSERVER_KAFKA_PRODUCER_TOPIC)
+  --kafka-consumer-group <GROUP>
+                           Kafka consumer group (env: AMSERVER_KAFKA_CONSUMER_GROUP)
+  -h, --help               Print this message";
+
+#[derive(Resource, Deserialize, Debug, Clone, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct ServerConfig {
+	pub network: NetworkConfig,
+	pub kafka: KafkaConfig,
+	pub battle: BattleConfig,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct NetworkConfig {
+	pub bind_address: IpAddr,
+	pub port: u16,
+	pub certificate: CertificateConfig,
+	// Most battles the server hosts at once.
+	pub max_sessions: usize,
+}
+
+impl Default for NetworkConfig {
+	fn default() -> Self {
+		NetworkConfig {
+			bind_address: IpAddr::V4(Ipv4Addr::UNSPECIFIED),
+			port: 6000,
+			certificate: CertificateConfig::default(),
+			max_sessions: 16,
+		}
+	}
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(tag = "mode", rename_all = "snake_case", deny_unknown_fields)]
+pub enum CertificateConfig {
+	GenerateSelfSigned {
+		server_hostname: String,
+	},
+	LoadFromFile {
+		cert_file: String,
+		key_file: String,
+	},
+	LoadFromFileOrGenerateSelfSigned {
+		cert_file: String,
+		key_file: String,
+		save_on_disk: bool,
+		server_hostname: String,
+	},
+}
+
+impl Default for CertificateConfig {
+	fn default() -> Self {
+		CertificateConfig::GenerateSelfSigned {
+			server_hostname: "amserver".to_string(),
+		}
+	}
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct KafkaConfig {
+	pub hosts: Vec<String>,
+	pub consumer_topic: String,
+	pub producer_topic: String,
+	pub consumer_group: String,
+}
+
+impl Default for KafkaConfig {
+	fn default() -> Self {
+		KafkaConfig {
+			hosts: vec!["139.162.244.70:9092".to_string()],
+			consumer_topic: "topic1".to_string(),
+			producer_topic: "topic2".to_string(),
+			consumer_group: "testgroup".to_string(),
+		}
+	}
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct BattleConfig {
+	pub map_file: String,
+	pub units_file: String,
+	pub skills_file: String,
+	// Whether units standing in the way block ranged attacks.
+	pub units_block_line_of_sight: bool,
+	// Seed of the random rolls of the battle. A new one is picked for every
+	// battle if not set.
+	pub seed: Option<u64>,
+	// Teams of the battle. Each team has a slot, taken by a client or by
+	// the AI.
+	pub teams: Vec<usize>,
+	// Teams played by the server instead of a client.
+	pub ai_teams: Vec<usize>,
+	// Teams that must be played by a client, or by the AI, before the host
+	// can start the battle.
+	pub required_teams: Vec<usize>,
+	// Scenarios the host can pick in the lobby, besides the default one.
+	pub scenarios: Vec<ScenarioConfig>,
+}
+
+impl Default for BattleConfig {
+	fn default() -> Self {
+		BattleConfig {
+			map_file: "src/the_patrol_ambush.map".to_string(),
+			units_file: "src/the_patrol_ambush_data.csv".to_string(),
+			skills_file: "src/skills.toml".to_string(),
+			units_block_line_of_sight: false,
+			seed: None,
+			teams: vec![1, 2],
+			ai_teams: Vec::new(),
+			required_teams: vec![1, 2],
+			scenarios: Vec::new(),
+		}
+	}
+}
+
+impl BattleConfig {
+	pub fn scenario(&self, name: &str) -> Option<ScenarioConfig> {
+		if name == DEFAULT_SCENARIO {
+			return Some(ScenarioConfig {
+				name: DEFAULT_SCENARIO.to_string(),
+				map_file: self.map_file.clone(),
+				units_file: self.units_file.clone(),
+			});
+		}
+		self.scenarios.iter().find(|scenario| scenario.name == name).cloned()
+	}
+
+	// Check that the AI and required teams are teams of the battle.
+	fn validate(&self) -> Result<(), ConfigError> {
+		for (option, teams) in [("battle.ai_teams", &self.ai_teams), ("battle.required_teams", &self.required_teams)] {
+			if let Some(team) = teams.iter().find(|team| !self.teams.contains(team)) {
+				return Err(ConfigError::InvalidValue { option: option.to_string(), value: team.to_string() });
+			}
+		}
+		Ok(())
+	}
+
+	pub fn scenario_names(&self) -> Vec<String> {
+		let mut names = vec![DEFAULT_SCENARIO.to_string()];
+		names.extend(self.scenarios.iter().map(|scenario| scenario.name.clone()));
+		names
+	}
+}
+
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct ScenarioConfig {
+	pub name: String,
+	pub map_file: String,
+	pub units_file: String,
+}
+
+#[derive(Debug)]
+pub enum ConfigError {
+	Io { path: String, error: io::Error },
+	Parse { path: String, error: toml::de::Error },
+	InvalidValue { option: String, value: String },
+	MissingValue { option: String },
+	UnknownArgument { argument: String },
+	HelpRequested,
+}
+
+impl fmt::Display for ConfigError {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match self {
+			ConfigError::Io { path, error } => write!(f, "could not read configuration file {}: {}", path, error),
+			ConfigError::Parse { path, error } => write!(f, "invalid configuration file {}: {}", path, error),
+			ConfigError::InvalidValue { option, value } => write!(f, "invalid value {:?} for {}", value, option),
+			ConfigError::MissingValue { option } => write!(f, "missing value for {}", option),
+			ConfigError::UnknownArgument { argument } => write!(f, "unknown argument {:?}", argument),
+			ConfigError::HelpRequested => write!(f, "{}", USAGE),
+		}
+	}
+}
+
+impl std::error::Error for ConfigError {}
+
+// Values given on the command line or through the environment. They take
+// precedence over the configuration file.
+struct Overrides {
+	config_path: Option<String>,
+	bind_address: Option<String>,
+	port: Option<String>,
+	cert_file: Option<String>,
+	key_file: Option<String>,
+	kafka_hosts: Option<String>,
+	kafka_consumer_topic: Option<String>,
+	kafka_producer_topic: Option<String>,
+	kafka_consumer_group: Option<String>,
+}
+
+impl Overrides {
+	fn from_env() -> Overrides {
+		Overrides {
+			config_path: env::var("AMSERVER_CONFIG").ok(),
+			bind_address: env::var("AMSERVER_BIND_ADDRESS").ok(),
+			port: env::var("AMSERVER_PORT").ok(),
+			cert_file: env::var("AMSERVER_CERT_FILE").ok(),
+			key_file: env::var("AMSERVER_KEY_FILE").ok(),
+			kafka_hosts: env::var("AMSERVER_KAFKA_HOSTS").ok(),
+			kafka_consumer_topic: env::var("AMSERVER_KAFKA_CONSUMER_TOPIC").ok(),
+			kafka_producer_topic: env::var("AMSERVER_KAFKA_PRODUCER_TOPIC").ok(),
+			kafka_consumer_group: env::var("AMSERVER_KAFKA_CONSUMER_GROUP").ok(),
+		}
+	}
+
+	// Command line arguments replace the values read from the environment.
+	fn apply_args(&mut self, args: impl IntoIterator<Item = String>) -> Result<(), ConfigError> {
+		let mut args = args.into_iter();
+		while let Some(argument) = args.next() {
+			let slot = match argument.as_str() {
+				"--config" => &mut self.config_path,
+				"--bind-address" => &mut self.bind_address,
+				"--port" => &mut self.port,
+				"--cert-file" => &mut self.cert_file,
+				"--key-file" => &mut self.key_file,
+				"--kafka-hosts" => &mut self.kafka_hosts,
+				"--kafka-consumer-topic" => &mut self.kafka_consumer_topic,
+				"--kafka-producer-topic" => &mut self.kafka_producer_topic,
+				"--kafka-consumer-group" => &mut self.kafka_consumer_group,
+				"-h" | "--help" => return Err(ConfigError::HelpRequested),
+				_ => return Err(ConfigError::UnknownArgument { argument }),
+			};
+			match args.next() {
+				Some(value) => *slot = Some(value),
+				None => return Err(ConfigError::MissingValue { option: argument }),
+			}
+		}
+		Ok(())
+	}
+}
+
+impl ServerConfig {
+	// Build the configuration from the configuration file, the environment
+	// and the command line, in increasing order of precedence.
+	pub fn load() -> Result<ServerConfig, ConfigError> {
+		let mut overrides = Overrides::from_env();
+		overrides.apply_args(env::args().skip(1))?;
+
+		let mut config = match &overrides.config_path {
+			Some(path) => ServerConfig::from_file(path)?,
+			None => match fs::metadata(DEFAULT_CONFIG_PATH) {
+				Ok(_) => ServerConfig::from_file(DEFAULT_CONFIG_PATH)?,
+				Err(_) => ServerConfig::default(),
+			},
+		};
+		config.apply_overrides(overrides)?;
+		config.battle.validate()?;
+
+		Ok(config)
+	}
+
+	pub fn from_file(path: &str) -> Result<ServerConfig, ConfigError> {
+		let contents = fs::read_to_string(path).map_err(|error| ConfigError::Io { path: path.to_string(), error })?;
+		toml::from_str(&contents).map_err(|error| ConfigError::Parse { path: path.to_string(), error })
+	}
+
+	fn apply_overrides(&mut self, overrides: Overrides) -> Result<(), ConfigError> {
+		if let Some(bind_address) = overrides.bind_address {
+			self.network.bind_address = bind_address.parse().map_err(|_| ConfigError::InvalidValue { option: "bind address".to_string(), value: bind_address })?;
+		}
+		if let Some(port) = overrides.port {
+			self.network.port = port.parse().map_err(|_| ConfigError::InvalidValue { option: "port".to_string(), value: port })?;
+		}
+		match (overrides.cert_file, overrides.key_file) {
+			(Some(cert_file), Some(key_file)) => {
+				self.network.certificate = CertificateConfig::LoadFromFile { cert_file, key_file };
+			},
+			(Some(_), None) => return Err(ConfigError::MissingValue { option: "key file".to_string() }),
+			(None, Some(_)) => return Err(ConfigError::MissingValue { option: "cert file".to_string() }),
+			(None, None) => {},
+		}
+		if let Some(kafka_hosts) = overrides.kafka_hosts {
+			self.kafka.hosts = kafka_hosts.split(',')
+				.map(|host| host.trim().to_string())
+				.filter(|host| !host.is_empty())
+				.collect();
+		}
+		if let Some(consumer_topic) = overrides.kafka_consumer_topic {
+			self.kafka.consumer_topic = consumer_topic;
+		}
+		if let Some(producer_topic) = overrides.kafka_producer_topic {
+			self.kafka.producer_topic = producer_topic;
+		}
+		if let Some(consumer_group) = overrides.kafka_consumer_group {
+			self.kafka.consumer_group = consumer_group;
+		}
+		Ok(())
+	}
+}
+
+impl NetworkConfig {
+	pub fn server_configuration(&self) -> ServerConfiguration {
+		ServerConfiguration::from_ip(self.bind_address, self.port)
+	}
+
+	pub fn certificate_retrieval_mode(&self) -> CertificateRetrievalMode {
+		match &self.certificate {
+			CertificateConfig::GenerateSelfSigned { server_hostname } => CertificateRetrievalMode::GenerateSelfSigned {
+				server_hostname: server_hostname.clone(),
+			},
+			CertificateConfig::LoadFromFile { cert_file, key_file } => CertificateRetrievalMode::LoadFromFile {
+				cert_file: cert_file.clone(),
+				key_file: key_file.clone(),
+			},
+			CertificateConfig::LoadFromFileOrGenerateSelfSigned { cert_file, key_file, save_on_disk, server_hostname } => CertificateRetrievalMode::LoadFromFileOrGenerateSelfSigned {
+				cert_file: cert_file.clone(),
+				key_file: key_file.clone(),
+				save_on_disk: *save_on_disk,
+				server_hostname: server_hostname.clone(),
+			},
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn no_overrides() -> Overrides {
+		Overrides {
+			config_path: None,
+			bind_address: None,
+			port: None,
+			cert_file: None,
+			key_file: None,
+			kafka_hosts: None,
+			kafka_consumer_topic: None,
+			kafka_producer_topic: None,
+			kafka_consumer_group: None,
+		}
+	}
+
+	fn args(args: &[&str]) -> Vec<String> {
+		args.iter().map(|arg| arg.to_string()).collect()
+	}
+
+	fn battle_config(contents: &str) -> BattleConfig {
+		toml::from_str::<ServerConfig>(contents).unwrap().battle
+	}
+
+	#[test]
+	fn listens_on_every_interface_by_default() {
+		assert_eq!(ServerConfig::default().network.bind_address, IpAddr::V4(Ipv4Addr::UNSPECIFIED));
+	}
+
+	#[test]
+	fn reads_partial_configuration_files() {
+		let config: ServerConfig = toml::from_str("[network]\nport = 7000\n\n[kafka]\nconsumer_group = \"group\"\n").unwrap();
+
+		assert_eq!(config.network.port, 7000);
+		assert_eq!(config.network.max_sessions, NetworkConfig::default().max_sessions);
+		assert_eq!(config.kafka.consumer_group, "group");
+		assert_eq!(config.kafka.consumer_topic, KafkaConfig::default().consumer_topic);
+		assert!(toml::from_str::<ServerConfig>("[network]\nprot = 7000\n").is_err());
+	}
+
+	#[test]
+	fn ai_and_required_teams_must_be_teams_of_the_battle() {
+		assert!(BattleConfig::default().validate().is_ok());
+		assert!(battle_config("[battle]\nteams = [1, 2, 3]\nai_teams = [3]\nrequired_teams = [1, 3]\n").validate().is_ok());
+
+		let config = battle_config("[battle]\nteams = [1, 2]\nai_teams = [3]\n");
+		assert!(matches!(config.validate(), Err(ConfigError::InvalidValue { option, value }) if option == "battle.ai_teams" && value == "3"));
+
+		let config = battle_config("[battle]\nteams = [1, 2]\nrequired_teams = [1, 4]\n");
+		assert!(matches!(config.validate(), Err(ConfigError::InvalidValue { option, value }) if option == "battle.required_teams" && value == "4"));
+	}
+
+	#[test]
+	fn finds_scenarios_by_name() {
+		let config = battle_config("[battle]\nmap_file = \"a.map\"\n\n[[battle.scenarios]]\nname = \"night\"\nmap_file = \"night.map\"\nunits_file = \"night.csv\"\n");
+
+		assert_eq!(config.scenario_names(), vec![DEFAULT_SCENARIO.to_string(), "night".to_string()]);
+		assert_eq!(config.scenario(DEFAULT_SCENARIO).unwrap().map_file, "a.map");
+		assert_eq!(config.scenario("night").unwrap().units_file, "night.csv");
+		assert_eq!(config.scenario("day"), None);
+	}
+
+	#[test]
+	fn command_line_overrides_the_environment() {
+		let mut overrides = Overrides { port: Some("1".to_string()), kafka_consumer_topic: Some("env-topic".to_string()), ..no_overrides() };
+		overrides.apply_args(args(&["--port", "7000", "--kafka-consumer-topic", "topic"])).unwrap();
+
+		assert_eq!(overrides.port, Some("7000".to_string()));
+		assert_eq!(overrides.kafka_consumer_topic, Some("topic".to_string()));
+	}
+
+	#[test]
+	fn overrides_replace_the_configuration() {
+		let overrides = Overrides {
+			bind_address: Some("127.0.0.1".to_string()),
+			port: Some("7000".to_string()),
+			cert_file: Some("cert.pem".to_string()),
+			key_file: Some("key.pem".to_string()),
+			kafka_hosts: Some("a:1, b:2,".to_string()),
+			kafka_consumer_topic: Some("in".to_string()),
+			kafka_producer_topic: Some("out".to_string()),
+			kafka_consumer_group: Some("group".to_string()),
+			..no_overrides()
+		};
+		let mut config = ServerConfig::default();
+		config.apply_overrides(overrides).unwrap();
+
+		assert_eq!(config.network.bind_address, IpAddr::V4(Ipv4Addr::LOCALHOST));
+		assert_eq!(config.network.port, 7000);
+		assert!(matches!(config.network.certificate, CertificateConfig::LoadFromFile { .. }));
+		assert_eq!(config.kafka.hosts, vec!["a:1".to_string(), "b:2".to_string()]);
+		assert_eq!((config.kafka.consumer_topic.as_str(), config.kafka.producer_topic.as_str(), config.kafka.consumer_group.as_str()), ("in", "out", "group"));
+	}
+
+	#[test]
+	fn rejects_invalid_arguments() {
+		assert!(matches!(no_overrides().apply_args(args(&["--port"])), Err(ConfigError::MissingValue { .. })));
+		assert!(matches!(no_overrides().apply_args(args(&["--colour", "red"])), Err(ConfigError::UnknownArgument { .. })));
+		assert!(matches!(no_overrides().apply_args(args(&["--help"])), Err(ConfigError::HelpRequested)));
+
+		let overrides = Overrides { port: Some("port".to_string()), ..no_overrides() };
+		assert!(matches!(ServerConfig::default().apply_overrides(overrides), Err(ConfigError::InvalidValue { .. })));
+		let overrides = Overrides { cert_file: Some("cert.pem".to_string()), ..no_overrides() };
+		assert!(matches!(ServerConfig::default().apply_overrides(overrides), Err(ConfigError::MissingValue { .. })));
+	}
+}
@@ -1,18 +1,20 @@
 use kafka::consumer::Consumer;
 use bevy::prelude::*;
 
+use crate::config::KafkaConfig;
+
 #[derive(Debug, Resource)]
 pub struct GameConsumer {
     pub c: Consumer
 }
 
 impl GameConsumer {
-    pub fn new(topic: &str) -> Result<Self, kafka::error::Error> {
+    pub fn new(config: &KafkaConfig) -> Result<Self, kafka::error::Error> {
         println!("Creating new Consumer...");
-        match Consumer::from_hosts(vec!("139.162.244.70:9092".to_owned()))
-            .with_topic_partitions(topic.to_owned(), &[0])
+        match Consumer::from_hosts(config.hosts.clone())
+            .with_topic_partitions(config.consumer_topic.clone(), &[0])
             .with_fallback_offset(kafka::consumer::FetchOffset::Earliest)
-            .with_group("testgroup".to_owned())
+            .with_group(config.consumer_group.clone())
             .with_offset_storage(kafka::consumer::GroupOffsetStorage::Kafka)
             .create() {
                 Ok(consumer) => {
@@ -1,19 +1,22 @@
 use std::time::Duration;
 use kafka::producer::{Producer, Record, RequiredAcks};
 
+use crate::config::KafkaConfig;
+
 pub struct GameProducer {
-	pub p: Producer
+	pub p: Producer,
+	pub topic: String,
 }
 
 impl GameProducer {
-	pub fn new() -> Result<Self, kafka::error::Error> {
+	pub fn new(config: &KafkaConfig) -> Result<Self, kafka::error::Error> {
 		println!("Creating new Producer...");
-		match Producer::from_hosts(vec!("139.162.244.70:9092".to_owned()))
+		match Producer::from_hosts(config.hosts.clone())
 			.with_ack_timeout(Duration::from_secs(1))
 			.with_required_acks(RequiredAcks::One)
 			.create() {
 				Ok(producer) => {
-					Ok(Self{p: producer})
+					Ok(Self{p: producer, topic: config.producer_topic.clone()})
 				},
 				Err(e) => {
 					println!("There was an error connecting to the kafka broker: {}", e);
@@ -24,7 +27,7 @@ impl GameProducer {
 	
 	pub fn send (mut self, value: String) {
 		println!("DEBUG: Sending record to Kafka...");
-		self.p.send(&Record::from_value("topic2", value.as_bytes())).unwrap();
+		self.p.send(&Record::from_value(&self.topic, value.as_bytes())).unwrap();
 		println!("DEBUG: Sent record to Kafka.");
 	}
 	
@@ -0,0 +1,177 @@
+// (C) Copyright 2023 Ars Militaris Dev
+
+// Lobby.
+//
+// Before a battle, clients gather in the lobby, where they pick their team
+// and tell the others they are ready. The first client to join is the host:
+// it picks the scenario, and starts the battle once the required teams are
+// played and every client is ready. When the host leaves, the next client to
+// have joined becomes the host.
+
+use bevy::prelude::*;
+
+use bevy_quinnet::shared::ClientId;
+
+use serde::{Deserialize, Serialize};
+
+use std::collections::HashSet;
+
+use crate::config::ScenarioConfig;
+use crate::teams::{SlotOccupant, TeamSlots};
+use crate::ActionRejectedReason;
+
+// A client in the lobby, as sent to the clients.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub struct LobbyPlayer {
+	pub client_id: ClientId,
+	pub team: Option<usize>,
+	pub ready: bool,
+	pub host: bool,
+}
+
+#[derive(Resource, Debug, Clone)]
+pub struct Lobby {
+	// Clients in the lobby, in the order they joined.
+	clients: Vec<ClientId>,
+	ready: HashSet<ClientId>,
+	pub scenario: ScenarioConfig,
+}
+
+impl Lobby {
+	pub fn new(scenario: ScenarioConfig) -> Lobby {
+		Lobby {
+			clients: Vec::new(),
+			ready: HashSet::new(),
+			scenario,
+		}
+	}
+
+	pub fn join(&mut self, client_id: ClientId) {
+		if !self.clients.contains(&client_id) {
+			self.clients.push(client_id);
+		}
+	}
+
+	pub fn leave(&mut self, client_id: ClientId) {
+		self.clients.retain(|client| *client != client_id);
+		self.ready.remove(&client_id);
+	}
+
+	pub fn host(&self) -> Option<ClientId> {
+		self.clients.first().copied()
+	}
+
+	pub fn set_ready(&mut self, client_id: ClientId, ready: bool) {
+		if ready && self.clients.contains(&client_id) {
+			self.ready.insert(client_id);
+		} else {
+			self.ready.remove(&client_id);
+		}
+	}
+
+	// Ask every client to be ready again, e.g. for a new scenario.
+	pub fn unready_all(&mut self) {
+		self.ready.clear();
+	}
+
+	pub fn players(&self, slots: &TeamSlots) -> Vec<LobbyPlayer> {
+		self.clients.iter()
+			.map(|client_id| LobbyPlayer {
+				client_id: *client_id,
+				team: slots.team_of(*client_id),
+				ready: self.ready.contains(client_id),
+				host: self.host() == Some(*client_id),
+			})
+			.collect()
+	}
+
+	// Check that `client_id` can start the battle: it has to be the host,
+	// every team in `required_teams` has to be played, and every client has
+	// to be ready.
+	pub fn check_start(&self, client_id: ClientId, slots: &TeamSlots, required_teams: &[usize]) -> Result<(), ActionRejectedReason> {
+		if self.host() != Some(client_id) {
+			return Err(ActionRejectedReason::NotHost);
+		}
+		if slots.slots().iter().any(|slot| required_teams.contains(&slot.team) && slot.occupant == SlotOccupant::Open) {
+			return Err(ActionRejectedReason::RequiredTeamOpen);
+		}
+		if self.clients.iter().any(|client| !self.ready.contains(client)) {
+			return Err(ActionRejectedReason::PlayersNotReady);
+		}
+		Ok(())
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn lobby() -> Lobby {
+		Lobby::new(ScenarioConfig { name: "test".to_string(), map_file: "test.map".to_string(), units_file: "test.csv".to_string(), })
+	}
+
+	#[test]
+	fn the_first_client_to_join_is_the_host() {
+		let mut lobby = lobby();
+		assert_eq!(lobby.host(), None);
+
+		lobby.join(10);
+		lobby.join(11);
+		lobby.join(10);
+		assert_eq!(lobby.host(), Some(10));
+
+		lobby.leave(10);
+		assert_eq!(lobby.host(), Some(11));
+	}
+
+	#[test]
+	fn lists_the_players_with_their_team() {
+		let mut lobby = lobby();
+		let mut slots = TeamSlots::new(&[1, 2], &[]);
+		lobby.join(10);
+		lobby.join(11);
+		slots.join(10);
+		lobby.set_ready(11, true);
+
+		assert_eq!(lobby.players(&slots), vec![
+			LobbyPlayer { client_id: 10, team: Some(1), ready: false, host: true, },
+			LobbyPlayer { client_id: 11, team: None, ready: true, host: false, },
+		]);
+	}
+
+	#[test]
+	fn only_the_host_starts_once_teams_are_played_and_everyone_is_ready() {
+		let mut lobby = lobby();
+		let mut slots = TeamSlots::new(&[1, 2, 3], &[]);
+		lobby.join(10);
+		lobby.join(11);
+		slots.join(10);
+
+		assert_eq!(lobby.check_start(11, &slots, &[1, 2]), Err(ActionRejectedReason::NotHost));
+		assert_eq!(lobby.check_start(10, &slots, &[1, 2]), Err(ActionRejectedReason::RequiredTeamOpen));
+
+		slots.join(11);
+		assert_eq!(lobby.check_start(10, &slots, &[1, 2]), Err(ActionRejectedReason::PlayersNotReady));
+
+		lobby.set_ready(10, true);
+		lobby.set_ready(11, true);
+		assert_eq!(lobby.check_start(10, &slots, &[1, 2]), Ok(()));
+
+		lobby.unready_all();
+		assert_eq!(lobby.check_start(10, &slots, &[1, 2]), Err(ActionRejectedReason::PlayersNotReady));
+	}
+
+	#[test]
+	fn clients_outside_the_lobby_are_never_ready() {
+		let mut lobby = lobby();
+		lobby.join(10);
+		lobby.set_ready(11, true);
+		lobby.set_ready(10, true);
+		lobby.leave(10);
+		lobby.join(10);
+
+		assert_eq!(lobby.players(&TeamSlots::new(&[1], &[])), vec![
+			LobbyPlayer { client_id: 10, team: None, ready: false, host: true, },
+		]);
+	}
+}
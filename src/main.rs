@@ -5,11 +5,6 @@ use bevy::{prelude::*, render::camera::ScalingMode, log::LogPlugin, diagnostic::
 use bevy::ecs::system::SystemParam;
 use bevy::ecs::world::World;
 
-use bevy::utils::Duration;
-
-use gridly_grids::VecGrid;
-use gridly::prelude::*;
-
 use std::fs;
 use std::collections::HashMap;
 
@@ -20,20 +15,43 @@ use kafka::producer::{Producer, Record, RequiredAcks};
 
 use bevy_quinnet::{
     server::{
-        certificate::CertificateRetrievalMode, ConnectionLostEvent, Endpoint, QuinnetServerPlugin,
-        Server, ServerConfiguration,
+        ConnectionLostEvent, QuinnetServerPlugin, Server,
     },
     shared::ClientId,
 };
 
 use serde::{Deserialize, Serialize};
 
-use rand::Rng;
-
-use pathfinding::prelude::astar;
-use std::cell::RefCell;
+use rand::SeedableRng;
+use rand_chacha::ChaCha8Rng;
 
 pub mod kafka_am;
+pub mod config;
+pub mod combat;
+mod battle_map;
+mod battle_grid;
+mod terrain;
+mod skills;
+mod targeting;
+mod status;
+mod turn_order;
+mod ai;
+mod teams;
+mod lobby;
+mod sessions;
+
+use config::ServerConfig;
+use combat::{AttackAngle, AttackOutcome, AttackResult, AttackStats, Combatant, DefenseStats, Side};
+use battle_map::BattleMap;
+use battle_grid::BattleGrid;
+use skills::{CasterStats, SkillBook, SkillOutcome};
+use targeting::Shape;
+use status::{StatusEffect, StatusEffects, StatusKind};
+use turn_order::{ScheduledUnit, TurnActions, WTProgress, FORECAST_TURNS};
+use ai::AiProfile;
+use teams::{TeamSlot, TeamSlots};
+use lobby::{Lobby, LobbyPlayer};
+use sessions::{SessionEndpoint, SessionId, SessionInfo, SessionServer};
 
 #[derive(Serialize, Deserialize)]
 enum ClientMessage {
@@ -49,7 +67,27 @@ enum ClientMessage {
 	BasicAttack {
 		attacker: Pos,
 		target: Pos,
-		damage: usize,
+	},
+	Face {
+		direction: Direction,
+	},
+	UseSkill {
+		skill_id: String,
+		target: Pos,
+	},
+	ChooseTeam {
+		team: usize,
+	},
+	SetReady {
+		ready: bool,
+	},
+	ChooseScenario {
+		scenario: String,
+	},
+	ListSessions,
+	CreateSession,
+	JoinSession {
+		session_id: SessionId,
 	},
 }
 
@@ -62,12 +100,44 @@ enum ServerMessage {
 		client_id: ClientId,
 	},
 	StartGame2,
+	TeamSlots {
+		slots: Vec<TeamSlot>,
+	},
+	Sessions {
+		sessions: Vec<SessionInfo>,
+	},
+	SessionJoined {
+		session_id: SessionId,
+	},
+	// The session failed, and its clients are out of it.
+	SessionClosed {
+		session_id: SessionId,
+	},
+	Lobby {
+		players: Vec<LobbyPlayer>,
+		slots: Vec<TeamSlot>,
+		scenario: String,
+		scenarios: Vec<String>,
+	},
 	PlayerTurn {
-		client_id: ClientId,
+		// Client playing the team of the current unit, if any.
+		client_id: Option<ClientId>,
 		current_unit: usize,
+		status_effects: Vec<StatusEffect>,
+		status_damage: usize,
+		turn_order: Vec<usize>,
+	},
+	TurnSkipped {
+		current_unit: usize,
+		status_effects: Vec<StatusEffect>,
+		status_damage: usize,
 	},
 	WaitTurn {
 		wait_turns: Vec<(UnitId, WTCurrent)>,
+		status_effects: Vec<(UnitId, Vec<StatusEffect>)>,
+	},
+	TurnOrder {
+		turns: Vec<usize>,
 	},
 	Wait,
 	Move {
@@ -77,17 +147,67 @@ enum ServerMessage {
 	BasicAttack {
 		attacker: Pos,
 		target: Pos,
-		damage: usize,
+		outcome: AttackOutcome,
 		is_counterattack: bool,
+		angle: AttackAngle,
+		inflicted_status: Option<StatusKind>,
+	},
+	Face {
+		pos: Pos,
+		direction: Direction,
+	},
+	UseSkill {
+		caster: Pos,
+		skill_id: String,
+		target: Pos,
+		caster_mp: usize,
+		outcomes: Vec<(Pos, SkillOutcome)>,
 	},
 	GameOver {
-		winner: ControlledBy,
-	}
+		// Team of the winner, or `None` if no unit is left.
+		winner: Option<usize>,
+		winner_slot: Option<TeamSlot>,
+	},
+	ActionRejected {
+		reason: ActionRejectedReason,
+	},
+	BattleLoadFailed {
+		reason: String,
+	},
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+enum ActionRejectedReason {
+	NoCurrentUnit,
+	NotYourUnit,
+	InvalidOrigin,
+	DestinationUnreachable,
+	ActionInProgress,
+	InvalidAttacker,
+	TargetOutOfRange,
+	LineOfSightBlocked,
+	NoTargetUnit,
+	TargetIsAlly,
+	UnknownSkill,
+	NotEnoughMp,
+	AlreadyMoved,
+	AlreadyActed,
+	TeamUnavailable,
+	NotHost,
+	UnknownScenario,
+	RequiredTeamOpen,
+	PlayersNotReady,
+	UnknownSession,
+	SessionInProgress,
+	TooManySessions,
 }
 
 struct PlayerTurnMessage {
-	client_id: ClientId,
+	client_id: Option<ClientId>,
 	current_unit: usize,
+	status_effects: Vec<StatusEffect>,
+	status_damage: usize,
+	turn_order: Vec<usize>,
 }
 
 #[derive(Reflect)]
@@ -102,8 +222,13 @@ enum UnitAction {
 	},
 	BasicAttack {
 		target: Pos,
-		is_counterattack: bool,
-		damage: usize,
+	},
+	Face {
+		direction: Direction,
+	},
+	UseSkill {
+		skill_id: String,
+		target: Pos,
 	},
 	DoNothing,
 }
@@ -114,7 +239,7 @@ impl Default for UnitAction {
     }
 }
 
-#[derive(Reflect)]
+#[derive(Reflect, Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
 #[reflect(Default)]
 enum Direction {
 	East,
@@ -124,14 +249,26 @@ enum Direction {
 }
 
 impl Direction {
-	fn from_string(dir_string: String) -> Direction {
+	fn from_string(dir_string: &str) -> Option<Direction> {
 		
-		match dir_string.as_str() {
-			"East" => Direction::East,
-			"South" => Direction::South,
-			"West" => Direction::West,
-			"North" => Direction::North,
-			_ => panic!("Invalid Direction string: {}", dir_string),
+		match dir_string {
+			"East" => Some(Direction::East),
+			"South" => Some(Direction::South),
+			"West" => Some(Direction::West),
+			"North" => Some(Direction::North),
+			_ => None,
+		}
+	}
+}
+
+impl Direction {
+	// Step, along x and y, to the next tile in the direction.
+	fn offset(&self) -> (isize, isize) {
+		match self {
+			Direction::East => (1, 0),
+			Direction::South => (0, -1),
+			Direction::West => (-1, 0),
+			Direction::North => (0, 1),
 		}
 	}
 }
@@ -198,8 +335,17 @@ struct TalkAction {
 #[derive(Component)]
 struct BasicAttackAction {
 	target: Pos,
-	is_counterattack: bool,
-	damage: usize,
+}
+
+#[derive(Component)]
+struct FaceAction {
+	direction: Direction,
+}
+
+#[derive(Component)]
+struct SkillAction {
+	skill_id: String,
+	target: Pos,
 }
 
 #[derive(Component)]
@@ -211,6 +357,13 @@ struct UnitActions {
 	processing_unit_action: bool,
 }
 
+impl UnitActions {
+	// Whether the unit still has actions to carry out.
+	fn is_busy(&self) -> bool {
+		!self.unit_actions.is_empty() || self.processing_unit_action
+	}
+}
+
 #[derive(Reflect)]
 #[reflect(Default)]
 struct UnitActionTuple(UnitAction, f32);
@@ -227,17 +380,13 @@ struct Cursor {
 	y: usize,
 }
 
-#[derive(Component)]
-struct Map {
-	map: Vec<Vec<(usize, TileType, Vec<Entity>, Vec<Entity>)>>,
-}
-
-#[derive(Component)]
-struct Tile;
-
 #[derive(Component, Debug, Clone, PartialEq)]
 enum TileType {
 	Grass, 
+	Road,
+	Forest,
+	Water,
+	Wall,
 }
 
 #[derive(Component)]
@@ -297,6 +446,14 @@ struct MPMax { value: usize, }
 #[derive(Component)]
 struct MPCurrent { value: usize, }
 
+// Ids of the skills a unit can use, from the skill book.
+#[derive(Component)]
+struct Skills { skill_ids: Vec<String>, }
+
+// Status effect a unit's basic attacks apply to the units they hit.
+#[derive(Component)]
+struct AttackStatus { status: Option<StatusKind>, }
+
 #[derive(Component)]
 struct STR { value: usize, }
 
@@ -330,6 +487,11 @@ struct MovementRange { value: isize, }
 #[reflect(Default)]
 struct AttackRange { value: isize, }
 
+// Highest height difference a unit can climb or drop in a single step.
+#[derive(Component, Default, Reflect)]
+#[reflect(Default)]
+struct Jump { value: usize, }
+
 #[derive(Component, Default, Reflect, Clone, Copy)]
 #[reflect(Default)]
 enum AttackType {
@@ -339,11 +501,11 @@ enum AttackType {
 }
 
 impl AttackType {
-	fn from_string(string: String) -> AttackType {
-		match string.as_str() {
-			"Melee" => { return AttackType::Melee; },
-			"Ranged" => { return AttackType::Ranged; },
-			_ => { panic!("Invalid AttackType string: {}.", string); },
+	fn from_string(string: &str) -> Option<AttackType> {
+		match string {
+			"Melee" => Some(AttackType::Melee),
+			"Ranged" => Some(AttackType::Ranged),
+			_ => None,
 		}
 	}
 }
@@ -373,6 +535,11 @@ struct UnitAttributes {
 	movement_range: MovementRange,
 	attack_range: AttackRange,
 	attack_type: AttackType,
+	jump: Jump,
+	skills: Skills,
+	attack_status: AttackStatus,
+	status_effects: StatusEffects,
+	ai_profile: AiProfile,
 }
 
 // STATES
@@ -381,6 +548,7 @@ struct UnitAttributes {
 enum GameState {
 	#[default]
 	MainMenu,
+	Lobby,
 	Loading,
 	ClientsLoading,
 	LoadMap,
@@ -410,6 +578,11 @@ struct UnitsReadEvent {
 #[derive(Event)]
 struct UnitsGeneratedEvent;
 
+#[derive(Event)]
+struct BattleLoadFailedEvent {
+	pub reason: String,
+}
+
 // RESOURCES
 
 #[derive(Resource)]
@@ -418,7 +591,7 @@ struct Game {
 	current_team: usize,
 	has_started: bool,
 	players: HashMap<usize, ControlledBy>,
-	winner: ControlledBy,
+	winner: Option<usize>,
 }
 
 impl Default for Game {
@@ -428,12 +601,12 @@ impl Default for Game {
             current_team: 1,
             has_started: false,
             players: HashMap::new(),
-            winner: ControlledBy::None, 
+            winner: None, 
         }
     }
 }
 
-#[derive(Serialize, Deserialize, Copy, Clone)]
+#[derive(Serialize, Deserialize, Debug, Copy, Clone, PartialEq)]
 enum ControlledBy {
 	Player,
 	AI,
@@ -451,6 +624,44 @@ struct PlayerTurnMessages {
 	messages: Vec<(PlayerTurnMessage, Timer)>,
 }
 
+#[derive(Resource, Default)]
+struct CombatMessages {
+	messages: Vec<(ServerMessage, Timer)>,
+}
+
+// Ids of the units taking the upcoming turns, as last sent to the clients.
+#[derive(Resource, Default)]
+struct TurnOrder {
+	turns: Vec<usize>,
+}
+
+// What the actions of clients are checked against.
+#[derive(SystemParam)]
+struct BattleRules<'w, 's> {
+	grid: Res<'w, BattleGrid>,
+	skill_book: Res<'w, SkillBook>,
+	config: Res<'w, ServerConfig>,
+	game: Res<'w, Game>,
+	slots: Res<'w, TeamSlots>,
+	unit_teams: Query<'w, 's, &'static UnitTeam>,
+}
+
+// What the lobby is made of.
+#[derive(SystemParam)]
+struct LobbyContext<'w> {
+	lobby: ResMut<'w, Lobby>,
+	slots: ResMut<'w, TeamSlots>,
+	config: Res<'w, ServerConfig>,
+}
+
+// Source of every random roll of the current battle. Rolls only depend on
+// the seed and on the actions taken, so a battle can be played again from
+// its seed.
+#[derive(Resource)]
+struct BattleRng {
+	rng: ChaCha8Rng,
+}
+
 #[derive(Resource, Default)]
 struct PlayerLoadings {
 	loadings: HashMap<ClientId, bool>,
@@ -459,6 +670,18 @@ struct PlayerLoadings {
 // Client & Server
 fn main() {
 	
+	let config = match ServerConfig::load() {
+		Ok(config) => config,
+		Err(config::ConfigError::HelpRequested) => {
+			println!("{}", config::USAGE);
+			std::process::exit(0);
+		},
+		Err(error) => {
+			eprintln!("amserver: {}", error);
+			std::process::exit(1);
+		},
+	};
+	
     App::new()
 		.add_plugins(MinimalPlugins)
 		.add_plugins(LogPlugin::default())
@@ -466,20 +689,44 @@ fn main() {
 		//.add_plugin(LogDiagnosticsPlugin::default())
         //.add_plugin(FrameTimeDiagnosticsPlugin::default())
 		.add_plugin(QuinnetServerPlugin::default())
+		.add_plugins(sessions::SessionsPlugin)
+		.insert_resource(config)
+		.add_systems(Startup, start_listening)
+		.run();
+}
+
+// Server
+// App of a session, which hosts one battle. See `sessions.rs`.
+fn session_app(session_id: SessionId, config: ServerConfig) -> App {
+	let team_slots = TeamSlots::new(&config.battle.teams, &config.battle.ai_teams);
+	let lobby = Lobby::new(config.battle.scenario(config::DEFAULT_SCENARIO).unwrap());
+	
+	let mut app = App::new();
+	app
+		.add_plugins(MinimalPlugins)
+		.add_plugins(ai::AiPlugin)
 		.add_state::<GameState>()
+		.add_event::<ConnectionLostEvent>()
 		.add_event::<GameStartEvent>()
 		.add_event::<MapReadEvent>()
 		.add_event::<MapSetupEvent>()
 		.add_event::<UnitsReadEvent>()
 		.add_event::<UnitsGeneratedEvent>()
+		.add_event::<BattleLoadFailedEvent>()
 		.init_resource::<Game>()
 		.init_resource::<Timers>()
 		.init_resource::<PlayerTurnMessages>()
 		.init_resource::<PlayerLoadings>()
-		.add_systems(OnEnter(GameState::MainMenu), start_listening)
+		.init_resource::<CombatMessages>()
+		.init_resource::<TurnOrder>()
+		.insert_resource(SessionServer::new(session_id))
+		.insert_resource(config)
+		.insert_resource(team_slots)
+		.insert_resource(lobby)
+		.add_systems(OnEnter(GameState::MainMenu), open_lobby)
 		.add_systems(Update,
 						handle_client_messages
-							.run_if(in_state(GameState::MainMenu))
+							.run_if(in_state(GameState::Lobby))
 		)
 //		.add_systems(Update,
 //						(read_map_system, setup_map_system, read_battle_system, generate_units_system, place_units_on_map_system)
@@ -495,13 +742,26 @@ fn main() {
 		.add_systems(Update, send_player_turn_messages
 							.run_if(in_state(GameState::Battle))
 		)
+		.add_systems(Update, send_turn_order
+							.run_if(in_state(GameState::WaitTurn))
+		)
+		.add_systems(Update, send_turn_order
+							.run_if(in_state(GameState::Battle))
+		)
 		.add_systems(Update, check_loadings.run_if(in_state(GameState::ClientsLoading)))
+		.add_systems(Update, handle_client_disconnections)
 		.add_systems(OnEnter(GameState::Loading), on_enter_loading_state)
 		.add_systems(OnEnter(GameState::Loading), setup_game_resource_system)
+		.add_systems(OnEnter(GameState::Loading), setup_battle_rng)
 		.add_systems(OnEnter(GameState::Loading), setup_grid_system)
+		.add_systems(OnEnter(GameState::Loading), setup_skill_book)
 		.add_systems(OnEnter(GameState::Loading), (apply_deferred, spawn_units)
 			.chain()
 			.after(setup_grid_system)
+			.after(setup_skill_book)
+		)
+		.add_systems(Update, handle_battle_load_failed
+			.run_if(in_state(GameState::Loading))
 		)
 		.add_systems(Update, tick_move_timer
 			.run_if(in_state(GameState::Move))
@@ -526,6 +786,17 @@ fn main() {
 			.chain()
 			.run_if(in_state(GameState::Battle))
 		)
+		.add_systems(Update, (apply_deferred, process_face_actions, apply_deferred)
+			.chain()
+			.run_if(in_state(GameState::Battle))
+		)
+		.add_systems(Update, (apply_deferred, process_skill_actions, apply_deferred)
+			.chain()
+			.run_if(in_state(GameState::Battle))
+		)
+		.add_systems(Update, send_combat_messages
+			.run_if(in_state(GameState::Battle))
+		)
 		.add_systems(Update, handle_unit_death
 			.run_if(in_state(GameState::Battle))
 		)
@@ -626,8 +897,8 @@ fn main() {
 						(wait_turn_system, handle_wait_turn_completed)
 							.run_if(in_state(GameState::WaitTurn))
 		)
-		.add_systems(OnExit(GameState::WaitTurn), on_complete_wait_turn)
-		.run();
+		.add_systems(OnExit(GameState::WaitTurn), on_complete_wait_turn);
+	app
 }
 
 // SYSTEMS
@@ -758,76 +1029,166 @@ fn main() {
 
 // Server
 fn wait_turn_system(
-mut units: Query<(Entity, &mut WTCurrent, &WTMax, &UnitId, &UnitTeam)>,
+mut units: Query<(Entity, &mut WTCurrent, &WTMax, &UnitId, &UnitTeam, &mut HPCurrent, &HPMax, &mut StatusEffects, &AGI, &mut WTProgress, &mut TurnActions)>,
 mut game: ResMut<Game>,
 mut commands: Commands,
-mut server: ResMut<Server>,
+mut server: ResMut<SessionServer>,
 mut next_state: ResMut<NextState<GameState>>,
 mut player_turn_messages: ResMut<PlayerTurnMessages>,
+slots: Res<TeamSlots>,
 ) {
 	
 	let endpoint = server.endpoint_mut();
 	
-	// Decrease all units WT. If WT equals 0, set the unit as the current unit turn.
-	for (entity, mut wt_current, wt_max, unit_id, unit_team) in units.iter_mut() {
-		if wt_current.value == 0 {
-			info!("DEBUG: It is now unit {} turn.", unit_id.value);
-			game.current_unit = unit_id.value;
-			
-			//// Send PlayerTurn message.
-			//info!("DEBUG: Sending Player Turn message...");
-			//endpoint.broadcast_message(ServerMessage::PlayerTurn { client_id: unit_team.value as u64, current_unit: unit_id.value, }).unwrap();
-			//info!("DEBUG: Sent Player Turn message.");
-			
-			// Schedule a PlayerTurn message for 0.5 seconds from now.
-			// This is a fix for BUG#6
-			player_turn_messages.messages.push((PlayerTurnMessage { client_id: unit_team.value as u64, current_unit: unit_id.value, }, Timer::from_seconds(0.5, TimerMode::Once)));
-			
-			// Assign the `CurrentUnit` component to the current unit.
-			commands.entity(entity).insert(CurrentUnit {});
-			
-			info!("DEBUG: Setting GameState to Battle..."); 
-			//commands.insert_resource(NextState(GameState::Battle));
-			next_state.set(GameState::Battle);
-			info!("DEBUG: Set GameState to Battle.");
-		} else {
-			wt_current.value = wt_current.value - 1;
+	// If a unit has reached 0 WT, it is its turn. When several units have,
+	// the one with the lowest unit id goes first.
+	let scheduled_units: Vec<ScheduledUnit> = units.iter()
+		.map(|(_, wt_current, wt_max, unit_id, _, _, _, status_effects, agi, wt_progress, _)| scheduled_unit(unit_id, wt_current, wt_max, agi, status_effects, wt_progress))
+		.collect();
+	let Some(ready_unit) = turn_order::next_ready(&scheduled_units) else {
+		// Otherwise, all units recover WT, at their own speed.
+		for (_, mut wt_current, _, unit_id, _, _, _, mut status_effects, agi, mut wt_progress, _) in units.iter_mut() {
+			let recovery_percent = turn_order::recovery_percent(agi.value, status_effects.wt_recovery_percent());
+			wt_current.value = wt_progress.recover(wt_current.value, recovery_percent);
+			status_effects.tick();
 			info!("DEBUG: Decreased unit {:?} WT. It is now {:?}.", unit_id.value, wt_current.value);
 		}
+		return;
+	};
+	
+	let Some((entity, mut wt_current, wt_max, unit_id, unit_team, mut hp_current, hp_max, mut status_effects, agi, wt_progress, mut turn_actions)) = units.iter_mut().find(|(_, _, _, unit_id, ..)| unit_id.value == ready_unit) else {
+		return;
+	};
+	*turn_actions = TurnActions::default();
+	
+	// The unit's status effects take effect as its turn starts.
+	let turn_start = status_effects.start_turn(&mut hp_current.value, hp_max.value);
+	if turn_start.hp_lost > 0 {
+		info!("DEBUG: Unit {} lost {} HP to its status effects. It now has {} HP.", unit_id.value, turn_start.hp_lost, hp_current.value);
+	}
+	
+	// A unit that can't act loses its turn, and waits again.
+	if turn_start.skips_turn {
+		info!("DEBUG: Unit {} loses its turn.", unit_id.value);
+		wt_current.value = turn_actions.wt_cost(wt_max.value);
+		endpoint.broadcast_message(ServerMessage::TurnSkipped {
+			current_unit: unit_id.value,
+			status_effects: status_effects.effects.clone(),
+			status_damage: turn_start.hp_lost,
+		}).unwrap();
+		return;
+	}
+	
+	info!("DEBUG: It is now unit {} turn.", unit_id.value);
+	game.current_unit = unit_id.value;
+	
+	// Assign the `CurrentUnit` component to the current unit.
+	commands.entity(entity).insert(CurrentUnit {});
+	
+	// Forecast the upcoming turns, starting with this one, now that the
+	// unit's status effects have changed.
+	let mut scheduled_units = scheduled_units;
+	for scheduled in scheduled_units.iter_mut().filter(|scheduled| scheduled.unit_id == unit_id.value) {
+		*scheduled = scheduled_unit(unit_id, &wt_current, wt_max, agi, &status_effects, &wt_progress);
+	}
+	
+	//// Send PlayerTurn message.
+	//info!("DEBUG: Sending Player Turn message...");
+	//endpoint.broadcast_message(ServerMessage::PlayerTurn { client_id: unit_team.value as u64, current_unit: unit_id.value, }).unwrap();
+	//info!("DEBUG: Sent Player Turn message.");
+	
+	// Schedule a PlayerTurn message for 0.5 seconds from now.
+	// This is a fix for BUG#6
+	player_turn_messages.messages.push((PlayerTurnMessage {
+		client_id: slots.client_of(unit_team.value),
+		current_unit: unit_id.value,
+		status_effects: status_effects.effects.clone(),
+		status_damage: turn_start.hp_lost,
+		turn_order: turn_order::forecast(scheduled_units, FORECAST_TURNS),
+	}, Timer::from_seconds(0.5, TimerMode::Once)));
+	
+	info!("DEBUG: Setting GameState to Battle..."); 
+	//commands.insert_resource(NextState(GameState::Battle));
+	next_state.set(GameState::Battle);
+	info!("DEBUG: Set GameState to Battle.");
+}
+
+// Server
+// A unit as seen by the turn order scheduler.
+fn scheduled_unit(unit_id: &UnitId, wt_current: &WTCurrent, wt_max: &WTMax, agi: &AGI, status_effects: &StatusEffects, wt_progress: &WTProgress) -> ScheduledUnit {
+	ScheduledUnit {
+		unit_id: unit_id.value,
+		wt_current: wt_current.value,
+		wt_max: wt_max.value,
+		recovery_percent: turn_order::recovery_percent(agi.value, status_effects.wt_recovery_percent()),
+		progress: *wt_progress,
+	}
+}
+
+// Server
+// Send the upcoming turns to the clients whenever they change, that is when
+// the WT or the speed of a unit changes, or a unit dies.
+fn send_turn_order(
+mut server: ResMut<SessionServer>,
+units: Query<(&UnitId, &WTCurrent, &WTMax, &AGI, &StatusEffects, &WTProgress)>,
+changed_units: Query<(), Or<(Changed<WTCurrent>, Changed<StatusEffects>)>>,
+mut removed_units: RemovedComponents<UnitId>,
+mut turn_order: ResMut<TurnOrder>,
+) {
+	let units_removed = removed_units.iter().count() > 0;
+	if changed_units.is_empty() && !units_removed {
+		return;
+	}
+	
+	let scheduled_units = units.iter()
+		.map(|(unit_id, wt_current, wt_max, agi, status_effects, wt_progress)| scheduled_unit(unit_id, wt_current, wt_max, agi, status_effects, wt_progress))
+		.collect();
+	let turns = turn_order::forecast(scheduled_units, FORECAST_TURNS);
+	if turns == turn_order.turns {
+		return;
 	}
+	
+	turn_order.turns = turns.clone();
+	let endpoint = server.endpoint_mut();
+	info!("DEBUG: Sending TurnOrder message...");
+	endpoint.broadcast_message(ServerMessage::TurnOrder { turns, }).unwrap();
+	info!("DEBUG: Sent TurnOrder message.");
 }
 
 // Server
-fn on_complete_wait_turn(mut server: ResMut<Server>, units: Query<(&UnitId, &WTCurrent)>) {
+fn on_complete_wait_turn(mut server: ResMut<SessionServer>, units: Query<(&UnitId, &WTCurrent, &StatusEffects)>) {
 	
 	// Build WaitTurn message.
 	let mut unit_wts: Vec<(UnitId, WTCurrent)> = Vec::new();
-	for (unit_id, current_wt) in units.iter() {
+	let mut unit_status_effects: Vec<(UnitId, Vec<StatusEffect>)> = Vec::new();
+	for (unit_id, current_wt, status_effects) in units.iter() {
 		unit_wts.push((unit_id.clone(), current_wt.clone()));
+		unit_status_effects.push((unit_id.clone(), status_effects.effects.clone()));
 	}
 	// Send WaitTurn message.
 	let endpoint = server.endpoint_mut();
 	info!("DEBUG: Sending WaitTurn message...");
 	endpoint.broadcast_message(ServerMessage::WaitTurn {
 		wait_turns: unit_wts,
+		status_effects: unit_status_effects,
 	}).unwrap();
 	info!("DEBUG: Sent WaitTurn message.");
 }
 
 // Server
 fn handle_wait_turn_completed (
-mut server: ResMut<Server>,
+mut server: ResMut<SessionServer>,
 mut commands: Commands,
-mut map_query: Query<&mut Map>,
-mut current_unit_query: Query<(Entity, &mut UnitActions, &mut WTCurrent, &WTMax), With<CurrentUnit>>,
+rules: BattleRules,
+mut current_unit_query: Query<(Entity, &mut UnitActions, &mut WTCurrent, &WTMax, &Pos, &UnitTeam, &MovementRange, &Jump, &AttackRange, &AttackType, &StatusEffects, &mut TurnActions), With<CurrentUnit>>,
+caster_query: Query<(&Skills, &MPCurrent), With<CurrentUnit>>,
 mut next_state: ResMut<NextState<GameState>>,
+mut combat_messages: ResMut<CombatMessages>,
 ) {
 	let mut endpoint = server.endpoint_mut();
 
-	let mut map = &mut map_query.single_mut().map;
-
 	for client_id in endpoint.clients() {
-		while let Ok(Some(message)) = endpoint.receive_message_from::<ClientMessage>(client_id) {
+		while let Ok(Some(message)) = endpoint.receive_message_from(client_id) {
 			match message {
 				ClientMessage::Wait => {
 					info!("DEBUG: Received Wait message.");
@@ -842,29 +1203,65 @@ mut next_state: ResMut<NextState<GameState>>,
 //						}
 //					}
 					
-					// Reset the current unit's WT.
-					let (entity, mut unit_actions, mut wt_current, wt_max) = current_unit_query.single_mut();
-					wt_current.value = wt_max.value;
-					info!("DEBUG: Reseted Current Unit's WT. It is now: {:?}.", wt_current);
+					let Ok((entity, unit_actions, mut wt_current, wt_max, _, unit_team, _, _, _, _, _, turn_actions)) = current_unit_query.get_single_mut() else {
+						reject_action(endpoint, client_id, ActionRejectedReason::NoCurrentUnit);
+						continue;
+					};
 					
-					// Remove the `CurrentUnit` component from current unit.
-					commands.entity(entity).remove::<CurrentUnit>();
+					// Only the client playing the unit's team can end its turn.
+					if !client_controls_team(&rules.slots, client_id, unit_team.value) {
+						reject_action(endpoint, client_id, ActionRejectedReason::NotYourUnit);
+						continue;
+					}
 					
-					// Send Wait message.
-					info!("DEBUG: Sending Wait message...");
-					endpoint.broadcast_message(ServerMessage::Wait).unwrap();
-					info!("DEBUG: Sent Wait message.");
+					// The turn ends once the unit is done with its actions.
+					if unit_actions.is_busy() {
+						reject_action(endpoint, client_id, ActionRejectedReason::ActionInProgress);
+						continue;
+					}
 					
-					info!("DEBUG: Setting GameState to WaitTurn...");
-					//commands.insert_resource(NextState(GameState::WaitTurn));
-					next_state.set(GameState::WaitTurn);
-					info!("DEBUG: Set GameState to WaitTurn...");
+					// Reset the current unit's WT, depending on what it did
+					// during its turn.
+					wt_current.value = turn_actions.wt_cost(wt_max.value);
+					info!("DEBUG: Reseted Current Unit's WT. It is now: {:?}.", wt_current);
+					
+					end_turn(endpoint, &mut commands, entity, &mut combat_messages, &mut next_state);
 				},
 				ClientMessage::Move { origin, destination } => {
 					info!("DEBUG: Received Move message from client {}.", client_id);
 					
+					let Ok((_, mut unit_actions, _, _, pos, unit_team, movement_range, jump, _, _, status_effects, mut turn_actions)) = current_unit_query.get_single_mut() else {
+						reject_action(endpoint, client_id, ActionRejectedReason::NoCurrentUnit);
+						continue;
+					};
+					
+					// Only the client playing the unit's team can move it.
+					if !client_controls_team(&rules.slots, client_id, unit_team.value) {
+						reject_action(endpoint, client_id, ActionRejectedReason::NotYourUnit);
+						continue;
+					}
+					
+					// A unit moves once per turn.
+					if turn_actions.moved {
+						reject_action(endpoint, client_id, ActionRejectedReason::AlreadyMoved);
+						continue;
+					}
+					
+					// Moves are checked from where the unit stands, so the
+					// unit must be done with its previous actions.
+					if unit_actions.is_busy() {
+						reject_action(endpoint, client_id, ActionRejectedReason::ActionInProgress);
+						continue;
+					}
+					
+					// Check that the move is legal before accepting it.
+					if let Err(reason) = validate_move(&rules.grid, pos, movement_points(movement_range, status_effects), jump, origin, destination) {
+						reject_action(endpoint, client_id, reason);
+						continue;
+					}
+					
 					// Insert `Move` `UnitAction` in the unit.
-					let (entity, mut unit_actions, mut wt_current, wt_max) = current_unit_query.single_mut();
+					turn_actions.moved = true;
 					unit_actions.unit_actions.push(UnitActionTuple(UnitAction::Move {
 						origin: Pos { x: origin.x, y: origin.y, },
 						destination: Pos { x: destination.x, y: destination.y },
@@ -878,24 +1275,122 @@ mut next_state: ResMut<NextState<GameState>>,
 					}).unwrap();
 					//info!("DEBUG: Sent `Move` message to clients.");
 				},
-				ClientMessage::BasicAttack { attacker, target, damage } => {
+				ClientMessage::BasicAttack { attacker, target } => {
 					info!("DEBUG: Received BasicAttack message from client {}.", client_id);
 					
+					let Ok((entity, mut unit_actions, _, _, pos, unit_team, _, _, attack_range, attack_type, _, mut turn_actions)) = current_unit_query.get_single_mut() else {
+						reject_action(endpoint, client_id, ActionRejectedReason::NoCurrentUnit);
+						continue;
+					};
+					
+					// Only the client playing the unit's team can attack with it.
+					if !client_controls_team(&rules.slots, client_id, unit_team.value) {
+						reject_action(endpoint, client_id, ActionRejectedReason::NotYourUnit);
+						continue;
+					}
+					
+					// A unit attacks or uses a skill once per turn.
+					if turn_actions.acted {
+						reject_action(endpoint, client_id, ActionRejectedReason::AlreadyActed);
+						continue;
+					}
+					
+					// Attacks are checked from where the unit stands, so the
+					// unit must be done with its previous actions.
+					if unit_actions.is_busy() {
+						reject_action(endpoint, client_id, ActionRejectedReason::ActionInProgress);
+						continue;
+					}
+					
+					// Check that the attack is legal and find the unit being attacked.
+					let target_entity = match validate_basic_attack(&rules.grid, pos, attack_range, attack_type, attacker, target, rules.config.battle.units_block_line_of_sight) {
+						Ok(target_entity) => target_entity,
+						Err(reason) => {
+							reject_action(endpoint, client_id, reason);
+							continue;
+						},
+					};
+					
+					// Units don't attack their own team.
+					if rules.unit_teams.get(target_entity).is_ok_and(|target_team| target_team.value == unit_team.value) {
+						reject_action(endpoint, client_id, ActionRejectedReason::TargetIsAlly);
+						continue;
+					}
+					
 					// Insert `BasicAttack` `UnitAction` in the unit.
-					let (entity, mut unit_actions, mut wt_current, wt_max) = current_unit_query.single_mut();
+					turn_actions.acted = true;
 					unit_actions.unit_actions.push(UnitActionTuple(UnitAction::BasicAttack {
 						target: Pos { x: target.x, y: target.y, },
-						is_counterattack: false,
-						damage: damage,
 					}, 0.0));
 					
 					// Insert an `Attacker` marker component on the attacking unit.
 					commands.entity(entity).insert(Attacker {});
 					
 					// Insert the `Target` marker component on the target unit.
-					let target_entity = map[target.x][target.y].2[0];
 					commands.entity(target_entity).insert(Target {});
 				}
+				ClientMessage::Face { direction } => {
+					info!("DEBUG: Received Face message from client {}.", client_id);
+					
+					let Ok((_, mut unit_actions, _, _, _, unit_team, _, _, _, _, _, _)) = current_unit_query.get_single_mut() else {
+						reject_action(endpoint, client_id, ActionRejectedReason::NoCurrentUnit);
+						continue;
+					};
+					
+					// Only the client playing the unit's team can turn it.
+					if !client_controls_team(&rules.slots, client_id, unit_team.value) {
+						reject_action(endpoint, client_id, ActionRejectedReason::NotYourUnit);
+						continue;
+					}
+					
+					// Insert `Face` `UnitAction` in the unit, so that the unit
+					// turns after finishing its previous actions. Clients are
+					// told once it does.
+					unit_actions.unit_actions.push(UnitActionTuple(UnitAction::Face {
+						direction,
+					}, 0.0));
+				},
+				ClientMessage::UseSkill { skill_id, target } => {
+					info!("DEBUG: Received UseSkill message from client {}.", client_id);
+					
+					let (Ok((_, mut unit_actions, _, _, pos, unit_team, _, _, _, _, _, mut turn_actions)), Ok((skills, mp_current))) = (current_unit_query.get_single_mut(), caster_query.get_single()) else {
+						reject_action(endpoint, client_id, ActionRejectedReason::NoCurrentUnit);
+						continue;
+					};
+					
+					// Only the client playing the unit's team can use its skills.
+					if !client_controls_team(&rules.slots, client_id, unit_team.value) {
+						reject_action(endpoint, client_id, ActionRejectedReason::NotYourUnit);
+						continue;
+					}
+					
+					// A unit attacks or uses a skill once per turn.
+					if turn_actions.acted {
+						reject_action(endpoint, client_id, ActionRejectedReason::AlreadyActed);
+						continue;
+					}
+					
+					// Skills are checked from where the unit stands, so the
+					// unit must be done with its previous actions.
+					if unit_actions.is_busy() {
+						reject_action(endpoint, client_id, ActionRejectedReason::ActionInProgress);
+						continue;
+					}
+					
+					// Check that the unit can use the skill on the target.
+					if let Err(reason) = validate_skill(&rules.grid, &rules.skill_book, pos, skills, mp_current, &skill_id, target) {
+						reject_action(endpoint, client_id, reason);
+						continue;
+					}
+					
+					// Insert `UseSkill` `UnitAction` in the unit. The result is
+					// sent to the clients once the skill is resolved.
+					turn_actions.acted = true;
+					unit_actions.unit_actions.push(UnitActionTuple(UnitAction::UseSkill {
+						skill_id,
+						target,
+					}, 0.0));
+				},
 				_ => { empty_system(); },
 			}
 		}
@@ -903,50 +1398,215 @@ mut next_state: ResMut<NextState<GameState>>,
 }
 
 // Server
-fn setup_game_resource_system(mut commands: Commands) {
+fn validate_move(
+grid: &BattleGrid,
+unit_pos: &Pos,
+movement_points: usize,
+jump: &Jump,
+origin: Pos,
+destination: Pos,
+) -> Result<(), ActionRejectedReason> {
+	// The move must start where the current unit stands.
+	if origin != *unit_pos {
+		return Err(ActionRejectedReason::InvalidOrigin);
+	}
+	
+	// The destination must be reachable with the unit's movement points and jump.
+	if !grid.reachable_tiles(*unit_pos, movement_points, jump.value).contains(destination) {
+		return Err(ActionRejectedReason::DestinationUnreachable);
+	}
+	
+	Ok(())
+}
+
+// Server
+// End the turn of the current unit, once its WT has been reset.
+fn end_turn(endpoint: &mut SessionEndpoint, commands: &mut Commands, entity: Entity, combat_messages: &mut CombatMessages, next_state: &mut NextState<GameState>) {
+	// Remove the `CurrentUnit` component from current unit.
+	commands.entity(entity).remove::<CurrentUnit>();
+	
+	// Send the rest of the last combat exchange before ending the turn.
+	flush_combat_messages(endpoint, combat_messages);
+	
+	// Send Wait message.
+	info!("DEBUG: Sending Wait message...");
+	endpoint.broadcast_message(ServerMessage::Wait).unwrap();
+	info!("DEBUG: Sent Wait message.");
+	
+	info!("DEBUG: Setting GameState to WaitTurn...");
+	//commands.insert_resource(NextState(GameState::WaitTurn));
+	next_state.set(GameState::WaitTurn);
+	info!("DEBUG: Set GameState to WaitTurn...");
+}
+
+// Server
+// Movement points a unit has this turn: its movement range, changed by its
+// status effects.
+fn movement_points(movement_range: &MovementRange, status_effects: &StatusEffects) -> usize {
+	(movement_range.value + status_effects.movement_modifier()).max(0) as usize
+}
+
+// Server
+fn validate_basic_attack(
+grid: &BattleGrid,
+unit_pos: &Pos,
+attack_range: &AttackRange,
+attack_type: &AttackType,
+attacker: Pos,
+target: Pos,
+units_block_line_of_sight: bool,
+) -> Result<Entity, ActionRejectedReason> {
+	// The attack must be made by the current unit.
+	if attacker != *unit_pos {
+		return Err(ActionRejectedReason::InvalidAttacker);
+	}
+	
+	// The target must be within the unit's attack range, and ranged units
+	// must have a clear shot at it.
+	match attack_type {
+		AttackType::Melee => {
+			if !find_possible_attacks(grid, *unit_pos, attack_range.value, *attack_type, units_block_line_of_sight).contains(&target) {
+				return Err(ActionRejectedReason::TargetOutOfRange);
+			}
+		},
+		AttackType::Ranged => {
+			let ranged_targets = find_ranged_targets(grid, *unit_pos, attack_range.value, units_block_line_of_sight);
+			if ranged_targets.obstructed.contains(&target) {
+				return Err(ActionRejectedReason::LineOfSightBlocked);
+			}
+			if !ranged_targets.targetable.contains(&target) {
+				return Err(ActionRejectedReason::TargetOutOfRange);
+			}
+		},
+	}
+	
+	// There must be a unit on the target tile.
+	grid.occupant(target).ok_or(ActionRejectedReason::NoTargetUnit)
+}
+
+// Server
+fn validate_skill(
+grid: &BattleGrid,
+skill_book: &SkillBook,
+unit_pos: &Pos,
+skills: &Skills,
+mp_current: &MPCurrent,
+skill_id: &str,
+target: Pos,
+) -> Result<(), ActionRejectedReason> {
+	// The unit must know the skill.
+	if !skills.skill_ids.iter().any(|known_skill_id| known_skill_id == skill_id) {
+		return Err(ActionRejectedReason::UnknownSkill);
+	}
+	let Some(skill) = skill_book.get(skill_id) else {
+		return Err(ActionRejectedReason::UnknownSkill);
+	};
+	
+	// The unit must have enough MP left.
+	if mp_current.value < skill.mp_cost {
+		return Err(ActionRejectedReason::NotEnoughMp);
+	}
+	
+	// The target must be within the skill's range.
+	if !skill.range.reach(grid, *unit_pos).contains(&target) {
+		return Err(ActionRejectedReason::TargetOutOfRange);
+	}
+	
+	// The skill must hit at least a unit.
+	if skill.area.units(grid, *unit_pos, target).is_empty() {
+		return Err(ActionRejectedReason::NoTargetUnit);
+	}
+	
+	Ok(())
+}
+
+// Server
+fn reject_action(endpoint: &mut SessionEndpoint, client_id: ClientId, reason: ActionRejectedReason) {
+	info!("DEBUG: Rejected action from client {}: {:?}.", client_id, reason);
+	endpoint.send_message(client_id, ServerMessage::ActionRejected { reason }).unwrap();
+}
+
+// Server
+fn setup_game_resource_system(mut commands: Commands, slots: Res<TeamSlots>) {
 	let mut players = HashMap::new();
-	players.insert(1, ControlledBy::Player);
-	players.insert(2, ControlledBy::Player);
+	for slot in slots.slots() {
+		players.insert(slot.team, slots.controller(slot.team));
+	}
 	
 	commands.insert_resource(Game {
 		current_unit: 0,
 		current_team: 1,
 		has_started: true,
 		players: players,
-		winner: ControlledBy::None,
+		winner: None,
 	});
 }
 
 // Server
-fn start_listening(mut server: ResMut<Server>) {
+fn setup_battle_rng(mut commands: Commands, config: Res<ServerConfig>, server: Res<SessionServer>) {
+	let seed = config.battle.seed.unwrap_or_else(rand::random);
+	info!("Battle seed of session {} is {}.", server.session_id(), seed);
+	
+	commands.insert_resource(BattleRng { rng: ChaCha8Rng::seed_from_u64(seed), });
+}
+
+// Server
+fn start_listening(mut server: ResMut<Server>, config: Res<ServerConfig>) {
+	info!("DEBUG: Listening on {}:{}...", config.network.bind_address, config.network.port);
 	server
 		.start_endpoint(
-			//ServerConfiguration::from_string("127.0.0.1:6000").unwrap(),
-			ServerConfiguration::from_string("139.162.244.70:6000").unwrap(),
-			CertificateRetrievalMode::GenerateSelfSigned {
-				server_hostname: "amserver".to_string(),
-			},
+			config.network.server_configuration(),
+			config.network.certificate_retrieval_mode(),
 		)
 		.unwrap();
 }
 
+// Server
+// Open the lobby for the next battle. Clients still connected after the last
+// battle stay in the lobby, and have to get ready again.
+fn open_lobby(
+mut server: ResMut<SessionServer>,
+mut game: ResMut<Game>,
+mut player_loadings: ResMut<PlayerLoadings>,
+mut lobby: LobbyContext,
+mut next_state: ResMut<NextState<GameState>>,
+) {
+	game.has_started = false;
+	for loaded in player_loadings.loadings.values_mut() {
+		*loaded = false;
+	}
+	
+	// Free the slots the AI took over for the last battle, if any.
+	lobby.slots.reset();
+	lobby.lobby.unready_all();
+	broadcast_lobby(server.endpoint_mut(), &lobby);
+	
+	info!("DEBUG: Setting GameState to Lobby...");
+	next_state.set(GameState::Lobby);
+	info!("DEBUG: Set GameState to Lobby.");
+}
+
 // Server
 fn handle_client_messages(
-    mut server: ResMut<Server>,
+    mut server: ResMut<SessionServer>,
     mut events: EventWriter<GameStartEvent>,
-    mut commands: Commands,
     mut game: ResMut<Game>,
-    mut units: Query<(&UnitId, &WTCurrent)>,
     mut next_state: ResMut<NextState<GameState>>,
     mut player_loadings: ResMut<PlayerLoadings>,
+    mut lobby: LobbyContext,
 ) {
-    let mut endpoint = server.endpoint_mut();
+    let endpoint = server.endpoint_mut();
     
     for client_id in endpoint.clients() {
-		while let Ok(Some(message)) = endpoint.receive_message_from::<ClientMessage>(client_id) {
+		while let Ok(Some(message)) = endpoint.receive_message_from(client_id) {
 			match message {
 				// Match on your own message types ...
 				ClientMessage::StartGame => {
+					if let Err(reason) = lobby.lobby.check_start(client_id, &lobby.slots, &lobby.config.battle.required_teams) {
+						reject_action(endpoint, client_id, reason);
+						continue;
+					}
+					
 					// Broadcast StartGame message.
 					info!("DEBUG: Sending StartGame message...");
 					endpoint.broadcast_message(ServerMessage::StartGame { client_id: client_id, }).unwrap();
@@ -954,35 +1614,19 @@ fn handle_client_messages(
 					
 					// If the server game hasn't started yet, start the game on the server.
 					if game.has_started == false {
-						info!("DEBUG: Starting game on server...");
+						info!("DEBUG: Starting game on server with scenario {}...", lobby.lobby.scenario.name);
 						game.has_started = true;
+						
+						// The AI plays the teams nobody has picked.
+						lobby.slots.fill_with_ai();
+						broadcast_team_slots(endpoint, &lobby.slots);
+						
 						events.send(GameStartEvent);
-						//info!("DEBUG: Setting GameState to Loading...");
 						info!("DEBUG: Setting GameState to ClientsLoading...");
-						//commands.insert_resource(NextState(GameState::Loading));
-						//next_state.set(GameState::Loading);
 						next_state.set(GameState::ClientsLoading);
 						info!("DEBUG: Set GameState to ClientsLoading.");
-					} else {
-						break;
-						// Send WaitTurn and PlayerTurn message to new client.
-						
-						// Build WaitTurn message.
-						let mut wts: Vec<(UnitId, WTCurrent)> = Vec::new();
-						for (unit_id, wt_current) in units.iter() {
-							wts.push((unit_id.clone(), wt_current.clone()));
-						}
-						info!("DEBUG: Sending WaitTurn message...");
-						endpoint.send_message(client_id, ServerMessage::WaitTurn {
-							wait_turns: wts,
-						}).unwrap();
-						info!("DEBUG: Sent WaitTurn message.");
-						//info!("DEBUG: Sending PlayerTurn message...");
-						//endpoint.send_message(client_id, ServerMessage::PlayerTurn {
-							//client_id: client_id,
-						//}).unwrap();
-						//info!("DEBUG: Sent PlayerTurn message.");
-					}               
+					}
+					break;
 				},
 				ClientMessage::GetClientId => {
 					// Register client and its load status.
@@ -993,6 +1637,48 @@ fn handle_client_messages(
 						client_id: client_id,
 					}).unwrap();
 					info!("DEBUG: Sent ClientId message.");
+					
+					// Give the client the first free team.
+					lobby.lobby.join(client_id);
+					match lobby.slots.join(client_id) {
+						Some(team) => info!("DEBUG: Client {} plays team {}.", client_id, team),
+						None => info!("DEBUG: No team left for client {}.", client_id),
+					}
+					broadcast_lobby(endpoint, &lobby);
+				},
+				ClientMessage::ChooseTeam { team } => {
+					match lobby.slots.choose_team(client_id, team) {
+						Ok(()) => {
+							info!("DEBUG: Client {} plays team {}.", client_id, team);
+							// The client has to get ready again in its new team.
+							lobby.lobby.set_ready(client_id, false);
+							broadcast_lobby(endpoint, &lobby);
+						},
+						Err(error) => {
+							info!("DEBUG: Client {} can't play team {}: {:?}.", client_id, team, error);
+							reject_action(endpoint, client_id, ActionRejectedReason::TeamUnavailable);
+						},
+					}
+				},
+				ClientMessage::SetReady { ready } => {
+					lobby.lobby.set_ready(client_id, ready);
+					broadcast_lobby(endpoint, &lobby);
+				},
+				ClientMessage::ChooseScenario { scenario } => {
+					if lobby.lobby.host() != Some(client_id) {
+						reject_action(endpoint, client_id, ActionRejectedReason::NotHost);
+						continue;
+					}
+					let Some(scenario) = lobby.config.battle.scenario(&scenario) else {
+						reject_action(endpoint, client_id, ActionRejectedReason::UnknownScenario);
+						continue;
+					};
+					
+					info!("DEBUG: Host picked scenario {}.", scenario.name);
+					lobby.lobby.scenario = scenario;
+					// Every client has to agree to the new scenario.
+					lobby.lobby.unready_all();
+					broadcast_lobby(endpoint, &lobby);
 				},
 				_ => { empty_system(); },
 			}
@@ -1001,11 +1687,11 @@ fn handle_client_messages(
 }
 
 // Server
-fn handle_wait_client_message(mut server: ResMut<Server>, mut commands: Commands, mut next_state: ResMut<NextState<GameState>>) {
+fn handle_wait_client_message(mut server: ResMut<SessionServer>, mut commands: Commands, mut next_state: ResMut<NextState<GameState>>) {
 	let mut endpoint = server.endpoint_mut();
 	
 	for client_id in endpoint.clients() {
-		while let Ok(Some(message)) = endpoint.receive_message_from::<ClientMessage>(client_id) {
+		while let Ok(Some(message)) = endpoint.receive_message_from(client_id) {
 			match message {
 				ClientMessage::Wait => {
 					info!("DEBUG: Received Wait message.");
@@ -1025,50 +1711,83 @@ fn handle_wait_client_message(mut server: ResMut<Server>, mut commands: Commands
 }
 
 // Client & Server
-fn setup_grid_system(mut commands: Commands) {
-	// Create map.
-	info!("DEBUG: Creating map...");
-	let mut map: Vec<Vec<(usize, TileType, Vec<Entity>, Vec<Entity>)>> = Vec::new();
-	for i in 0..30 {
-		let mut map_line: Vec<(usize, TileType, Vec<Entity>, Vec<Entity>)> = Vec::new();
-		for j in 0..30 {
-			map_line.push((1, TileType::Grass, Vec::new(), Vec::new()));
-		}
-		map.push(map_line);
-	}
-	
-	//for i in 0..3 {
-	//	map[i][0].0 = 10;
-	//}
-	
-	info!("DEBUG: Created map.");
+fn setup_grid_system(
+mut commands: Commands,
+lobby: Res<Lobby>,
+mut events: EventWriter<BattleLoadFailedEvent>,
+) {
+	// Read the battle's map file.
+	info!("DEBUG: Reading map file {}...", lobby.scenario.map_file);
+	let battle_map = match BattleMap::from_file(&lobby.scenario.map_file) {
+		Ok(battle_map) => battle_map,
+		Err(error) => {
+			events.send(BattleLoadFailedEvent { reason: error.to_string() });
+			return;
+		},
+	};
+	info!("DEBUG: Read {}x{} map.", battle_map.width, battle_map.height);
 	
-	commands.spawn((
-		Map { map: map },
-	));
+	// Create grid.
+	info!("DEBUG: Creating grid...");
+	let grid = BattleGrid::from_battle_map(&battle_map);
+	info!("DEBUG: Created {}x{} grid.", grid.width(), grid.height());
+	commands.insert_resource(grid);
 }
 
-// Client & Server
-fn grid_already_setup(query: Query<&Map>) -> bool {
-	if query.iter().len() == 0 {
-		return false;
-	} else {
-		return true;
+// Server
+fn setup_skill_book(
+mut commands: Commands,
+config: Res<ServerConfig>,
+mut events: EventWriter<BattleLoadFailedEvent>,
+) {
+	info!("DEBUG: Reading skill file {}...", config.battle.skills_file);
+	match SkillBook::from_file(&config.battle.skills_file) {
+		Ok(skill_book) => commands.insert_resource(skill_book),
+		Err(error) => events.send(BattleLoadFailedEvent { reason: error.to_string() }),
 	}
 }
 
-// Test
-fn test_system_2(
-map_query: Query<&Map>,
-mut transform_query: Query<&Transform>,
+// Server
+fn handle_battle_load_failed(
+mut commands: Commands,
+mut events: EventReader<BattleLoadFailedEvent>,
+mut server: ResMut<SessionServer>,
+mut game: ResMut<Game>,
+mut player_loadings: ResMut<PlayerLoadings>,
+mut next_state: ResMut<NextState<GameState>>,
+units: Query<Entity, With<Unit>>,
 ) {
-	let map = &map_query.single().map;
-	
-	let entity_id = map[1][0].3[0];
-	
-	if let Ok(transform) = transform_query.get(entity_id) {
-		info!("DEBUG: Tile at position (1, 0) is at coordinates: {:?}.", transform.translation);
+	if let Some(event) = events.iter().next() {
+		error!("Could not load battle: {}", event.reason);
+		
+		// Let the clients know the battle won't start.
+		let endpoint = server.endpoint_mut();
+		endpoint.broadcast_message(ServerMessage::BattleLoadFailed {
+			reason: event.reason.clone(),
+		}).unwrap();
+		
+		// Remove whatever was loaded, and go back to the main menu so that a
+		// new game can be started.
+		commands.remove_resource::<BattleGrid>();
+		commands.remove_resource::<SkillBook>();
+		for entity in units.iter() {
+			commands.entity(entity).despawn();
+		}
+		game.has_started = false;
+		for loaded in player_loadings.loadings.values_mut() {
+			*loaded = false;
+		}
+		
+		info!("DEBUG: Setting GameState to MainMenu...");
+		next_state.set(GameState::MainMenu);
+		info!("DEBUG: Set GameState to MainMenu.");
 	}
+	events.clear();
+}
+
+// Client & Server
+fn grid_already_setup(grid: Option<Res<BattleGrid>>) -> bool {
+	grid.is_some()
 }
 
 // Test
@@ -1082,14 +1801,14 @@ camera_transform_query: Query<&Transform, With<Camera>>,
 
 // Server
 fn handle_loading_complete_messages(
-mut server: ResMut<Server>,
+mut server: ResMut<SessionServer>,
 mut next_state: ResMut<NextState<GameState>>,
 mut player_loadings: ResMut<PlayerLoadings>,
 ) {
 	let endpoint = server.endpoint_mut();
 	
 	for client_id in endpoint.clients() {
-		while let Ok(Some(message)) = endpoint.receive_message_from::<ClientMessage>(client_id) {
+		while let Ok(Some(message)) = endpoint.receive_message_from(client_id) {
 			match message {
 				ClientMessage::LoadingComplete => {
 					info!("DEBUG: Received LoadingComplete message from client {}.", client_id);
@@ -1113,7 +1832,7 @@ mut player_loadings: ResMut<PlayerLoadings>,
 // Server
 fn check_loadings(
 player_loadings: Res<PlayerLoadings>,
-mut server: ResMut<Server>,
+mut server: ResMut<SessionServer>,
 mut next_state: ResMut<NextState<GameState>>,
 ) {
 	// Compute if all bools in the HashMap are set to true.
@@ -1133,6 +1852,38 @@ mut next_state: ResMut<NextState<GameState>>,
 	}
 }
 
+// Server
+fn handle_client_disconnections(
+mut events: EventReader<ConnectionLostEvent>,
+mut server: ResMut<SessionServer>,
+mut game: ResMut<Game>,
+mut player_loadings: ResMut<PlayerLoadings>,
+mut lobby: LobbyContext,
+) {
+	for event in events.iter() {
+		info!("DEBUG: Client {} disconnected.", event.id);
+		
+		// Don't wait for the client to load the battle.
+		player_loadings.loadings.remove(&event.id);
+		
+		// If the client was the host, the next client becomes the host.
+		lobby.lobby.leave(event.id);
+		let team = lobby.slots.leave(event.id);
+		
+		if game.has_started {
+			// Once the game has started, the AI plays the team of the client.
+			if let Some(team) = team {
+				info!("DEBUG: The AI takes over team {}.", team);
+				lobby.slots.fill_with_ai();
+				game.players.insert(team, ControlledBy::AI);
+			}
+			broadcast_team_slots(server.endpoint_mut(), &lobby.slots);
+		} else {
+			broadcast_lobby(server.endpoint_mut(), &lobby);
+		}
+	}
+}
+
 // Server
 fn on_enter_loading_state(
 mut events: EventWriter<GameStartEvent>,
@@ -1143,7 +1894,7 @@ mut events: EventWriter<GameStartEvent>,
 }
 
 // Server
-fn send_player_turn_messages(mut server: ResMut<Server>, mut player_turn_messages: ResMut<PlayerTurnMessages>, time: Res<Time>) {
+fn send_player_turn_messages(mut server: ResMut<SessionServer>, mut player_turn_messages: ResMut<PlayerTurnMessages>, time: Res<Time>) {
 
 	let mut messages = &mut player_turn_messages.messages;
 	if messages.len() == 0 {
@@ -1153,10 +1904,15 @@ fn send_player_turn_messages(mut server: ResMut<Server>, mut player_turn_message
 		
 		// Send PlayerTurn message.
 		info!("DEBUG: Sending Player Turn message...");
-		endpoint.broadcast_message(ServerMessage::PlayerTurn { client_id: messages[0].0.client_id, current_unit: messages[0].0.current_unit, }).unwrap();
+		let (message, _) = messages.remove(0);
+		endpoint.broadcast_message(ServerMessage::PlayerTurn {
+			client_id: message.client_id,
+			current_unit: message.current_unit,
+			status_effects: message.status_effects,
+			status_damage: message.status_damage,
+			turn_order: message.turn_order,
+		}).unwrap();
 		info!("DEBUG: Sent Player Turn message.");
-		
-		messages.remove(0);
 	}
 	
 	
@@ -1166,11 +1922,8 @@ fn send_player_turn_messages(mut server: ResMut<Server>, mut player_turn_message
 fn process_unit_actions(
 mut commands: Commands,
 mut unit_actions_query: Query<(Entity, &mut UnitActions)>,
-mut map_query: Query<&mut Map>,
 time: Res<Time>,
 ) {
-	let map = &map_query.single_mut().map;
-	
 	//info!("DEBUG: unit_actions_query length is: {}.", unit_actions_query.iter().len());
 	
 	for (entity, mut unit_actions) in unit_actions_query.iter_mut() {
@@ -1199,9 +1952,17 @@ time: Res<Time>,
 					info!("DEBUG: Current unit action is Talk.");
 					commands.entity(entity).insert(TalkAction { message: message.clone(), });
 				},
-				UnitAction::BasicAttack { target, is_counterattack, damage} => {
+				UnitAction::BasicAttack { target } => {
 					info!("DEBUG: Current unit action is BasicAttack.");
-					commands.entity(entity).insert(BasicAttackAction { target: target.clone(), is_counterattack: is_counterattack.clone(), damage: damage.clone(), });
+					commands.entity(entity).insert(BasicAttackAction { target: target.clone(), });
+				}
+				UnitAction::Face { direction } => {
+					info!("DEBUG: Current unit action is Face.");
+					commands.entity(entity).insert(FaceAction { direction: *direction, });
+				}
+				UnitAction::UseSkill { skill_id, target } => {
+					info!("DEBUG: Current unit action is UseSkill.");
+					commands.entity(entity).insert(SkillAction { skill_id: skill_id.clone(), target: *target, });
 				}
 				UnitAction::DoNothing => {
 					info!("DEBUG: Current unit action is DoNothing.");
@@ -1215,18 +1976,17 @@ time: Res<Time>,
 // Prototype
 fn process_move_actions(
 mut commands: Commands,
-mut map_query: Query<&mut Map>,
-mut unit_query: Query<(Entity, &mut UnitActions, &mut Pos, &MoveAction, &mut MoveActions)>,
+grid: Res<BattleGrid>,
+mut unit_query: Query<(Entity, &mut UnitActions, &mut Pos, &MoveAction, &mut MoveActions, &MovementRange, &Jump, &StatusEffects)>,
 mut next_state: ResMut<NextState<GameState>>,
 ) {
-	let map = &mut map_query.single_mut().map;
-	
-	for (entity, mut unit_actions, mut pos, move_action, mut move_actions) in unit_query.iter_mut() {
+	for (entity, mut unit_actions, mut pos, move_action, mut move_actions, movement_range, jump, status_effects) in unit_query.iter_mut() {
 		info!("DEBUG: Processing MoveAction...");
 		info!("DEBUG: Move destination is: {}, {}.", move_action.destination.x, move_action.destination.y);
 		
 		// Calculate path.
-		let path = find_path(map.to_vec(), move_action.origin, move_action.destination);
+		let reachability = grid.reachable_tiles(move_action.origin, movement_points(movement_range, status_effects), jump.value);
+		let path = reachability.path_to(move_action.destination);
 		if let Some(mut path) = path {
 			
 			let origin_backup: Pos = path[0];
@@ -1261,16 +2021,13 @@ mut next_state: ResMut<NextState<GameState>>,
 // Prototype
 fn handle_move_state(
 mut commands: Commands,
-mut map_query: Query<&mut Map>,
+mut grid: ResMut<BattleGrid>,
 mut unit_query: Query<(Entity, &mut UnitActions, &mut Pos, &mut MoveActions, &mut DIR), (With<MoveAction>, Without<GameText>)>,
 tile_transform_query: Query<&Transform, (With<GameText>, Without<Unit>)>,
 mut next_state: ResMut<NextState<GameState>>,
 game: Res<Game>,
 time: Res<Time>,
 ) {
-	let mut map_component = map_query.single_mut();
-	let mut map = &mut map_component.map;
-
 	if unit_query.iter_mut().len() == 0 {
 		
 		info!("DEBUG: No MoveActions remaining. Setting GameState to Battle.");
@@ -1291,7 +2048,7 @@ time: Res<Time>,
 			} else {
 				let move_action = &move_actions.move_actions[0];
 				
-				if map[move_action.destination.x][move_action.destination.y].2.len() > 0 {
+				if !grid.is_walkable(move_action.destination) {
 					info!("DEBUG: Couldn't move unit. There's an unit already there.");
 					unit_actions.processing_unit_action = false;
 					unit_actions.unit_actions.remove(0);
@@ -1302,8 +2059,7 @@ time: Res<Time>,
 					// Complete processing of MoveAction.
 						
 					info!("DEBUG: Completing processing of MoveAction...");
-					map[move_action.destination.x][move_action.destination.y].2.push(entity);
-					map[pos.x][pos.y].2.pop();
+					grid.move_occupant(*pos, move_action.destination);
 					
 					pos.x = move_action.destination.x;
 					pos.y = move_action.destination.y;
@@ -1347,128 +2103,115 @@ fn process_do_nothing_actions(mut commands: Commands, mut unit_query: Query<(Ent
 // Prototype
 fn process_basic_attack_actions(
 mut commands: Commands,
-map_query: Query<&Map>,
-mut attack_unit_query: Query<(Entity, &UnitId, &mut UnitActions, &STR, &Pos, &mut DIR, &BasicAttackAction), (With<Attacker>, Without<Target>)>,
-mut target_unit_query: Query<(&UnitId, &mut UnitActions, &Pos, &mut HPCurrent, &AttackRange, &AttackType), (With<Target>, Without<Attacker>)>,
-mut server: ResMut<Server>,
-time: Res<Time>,
+grid: Res<BattleGrid>,
+mut attack_unit_query: Query<(Entity, &UnitId, &mut UnitActions, &Pos, &mut DIR, &mut HPCurrent, &STR, &DEX, &LUK, &VIT, &AGI, &AttackStatus, &mut StatusEffects, &BasicAttackAction), (With<Attacker>, Without<Target>)>,
+mut target_unit_query: Query<(&UnitId, &Pos, &mut DIR, &mut HPCurrent, &STR, &DEX, &LUK, &VIT, &AGI, &AttackRange, &AttackType, &AttackStatus, &mut StatusEffects), (With<Target>, Without<Attacker>)>,
+mut server: ResMut<SessionServer>,
+mut combat_messages: ResMut<CombatMessages>,
+mut battle_rng: ResMut<BattleRng>,
 ) {
-	let mut endpoint = server.endpoint_mut();
-
-	let map = &map_query.single().map;
+	let endpoint = server.endpoint_mut();
 
-	for (entity, unit_id, mut unit_actions, str, pos, mut dir, basic_attack_action) in attack_unit_query.iter_mut() {
+	for (entity, unit_id, mut unit_actions, pos, mut dir, mut hp_current, str, dex, luk, vit, agi, attack_status, mut status_effects, basic_attack_action) in attack_unit_query.iter_mut() {
 		info!("DEBUG: Processing BasicAttack action...");
 		
-		// Get target entity from map.
-		let target_entity = map[basic_attack_action.target.x][basic_attack_action.target.y].2[0];
-		
-		// Get target health.
-		if let Ok((target_id, mut target_unit_actions, target_pos, mut hp_current, attack_range, attack_type)) = target_unit_query.get_mut(target_entity) {
-			// Change attacker's direction to face the target.
-			// Set the unit's direction.
-			if target_pos.x < pos.x {
-				dir.direction = Direction::West;
-			} else if target_pos.x > pos.x {
-				dir.direction = Direction::East;
-			}
-			if target_pos.y < pos.y {
-				dir.direction = Direction::South;
-			} else if target_pos.y > pos.y {
-				dir.direction = Direction::North;
-			}
-			
-			// Compute a random number between -3 to 3.
-			let mut rng = rand::thread_rng();
-			let random_dmg = rng.gen_range(0..7);
-			let random_dmg_modifier = random_dmg - 3;
-			
-			
-			// Add random modifier to damage.
-			// Damage is (STR / 3) + modifier.
-			let attack_damage = (str.value / 3) + random_dmg_modifier;
-			
-			// Send `BasicAttack` message to clients.
-			info!("DEBUG: Sending `BasicAttack` message to clients.");
-			match unit_actions.unit_actions[0].0 {
-				UnitAction::BasicAttack { target, is_counterattack, damage } => {
-					endpoint.broadcast_message(ServerMessage::BasicAttack {
-						attacker: Pos { x: pos.x, y: pos.y, },
-						target: Pos { x: target_pos.x, y: target_pos.y, },
-						damage: attack_damage.clone(),
-						is_counterattack: is_counterattack,
-					}).unwrap();
-					info!("DEBUG: Sent `BasicAttack` message to clients.");
-				},
-				_ => { empty_system(); },
-			}
-			
-			// Subtract damage from target HP.
-			if attack_damage > hp_current.value {
-				hp_current.value = 0;
+		// Get target entity from map. The target may have left the tile since
+		// the attack was ordered, in which case the attack hits nothing.
+		if let Some(target_entity) = grid.occupant(basic_attack_action.target) {
+			if let Ok((target_id, target_pos, mut target_dir, mut target_hp_current, target_str, target_dex, target_luk, target_vit, target_agi, attack_range, attack_type, target_attack_status, mut target_status_effects)) = target_unit_query.get_mut(target_entity) {
+				// Find where the attack comes from, before anyone turns.
+				let angle = attack_angle(target_pos, &target_dir.direction, pos);
 				
-				// Remove Attacker marker component.
-				commands.entity(entity).remove::<Attacker>();
-			
-				// Remove BasicAttack UnitAction.
-				unit_actions.unit_actions.remove(0);
-				unit_actions.processing_unit_action = false;
-				commands.entity(entity).remove::<BasicAttackAction>();
+				// Change attacker's direction to face the target.
+				dir.direction = direction_towards(pos, target_pos);
 				
-				info!("DEBUG: Processed BasicAttack action.");
+				// Ranged units don't counter-attack. Melee units do if the
+				// attacker is within their reach, which doesn't depend on line
+				// of sight.
+				let can_counter = match attack_type {
+					AttackType::Ranged => false,
+					AttackType::Melee => find_possible_attacks(&grid, *target_pos, attack_range.value, *attack_type, false).contains(pos),
+				};
 				
-				return;
-			} else {
-				hp_current.value -= attack_damage;
-			}
-			
-			info!("DEBUG: Unit {:?} did {:?} damage to unit {:?}.", unit_id, attack_damage, target_id);
-			info!("DEBUG: Unit {} now has {} HP.", target_id.value, hp_current.value);
-			
-			// Remove Target marker component from the target.
-			commands.entity(target_entity).remove::<Target>();
-			
-			match unit_actions.unit_actions[0].0 {
-				UnitAction::BasicAttack { target, is_counterattack, damage } => {
-					// If it is not already a counter-attack...
-					if !is_counterattack {
-						// If target is not a ranged unit...
-						// Insert a counter-attack.
-						match attack_type {
-							AttackType::Ranged => { 
-								info!("DEBUG: Target is a ranged unit. Won't make a counter-attack.");
-							},
-							AttackType::Melee => {
-								info!("DEBUG: Target is a melee unit. Will make a counter-attack if at range.");
-								let target_possible_attacks = find_possible_attacks(map.to_vec(), *target_pos, attack_range.value, *attack_type);
-								
-								if target_possible_attacks.contains(pos) {
-									// Insert a BasicAttack as a counter-attack.
-									
-									// Schedule a `BasicAttack` `ServerMessage` to
-									// 2 seconds from now, so that the client is
-									// able to process the first `BasicAttack`.
-									target_unit_actions.unit_actions.push(UnitActionTuple(UnitAction::BasicAttack {
-										target: Pos { x: pos.x, y: pos.y, },
-										is_counterattack: true,
-										damage: 0,
-									}, (time.elapsed() + Duration::from_secs(2)).as_secs() as f32));
-									
-									// Insert the Attacker marker component on the counter-attacking unit.
-									commands.entity(target_entity).insert(Attacker {});
-									
-									// Insert the Target marker component on the unit that did the initial attack.
-									// This will result in an infinite Attack and CounterAttack loop for now.
-									commands.entity(entity).insert(Target {});
-									
-								}
+				// Units get the bonuses of the terrain they stand on.
+				let terrain = grid.get(*pos).unwrap().terrain.properties();
+				let target_terrain = grid.get(*target_pos).unwrap().terrain.properties();
+				
+				// Resolve the whole exchange at once.
+				let strikes = combat::resolve_exchange(
+					&Combatant {
+						attack: AttackStats { str: str.value, dex: dex.value, luk: luk.value, },
+						defense: DefenseStats { vit: vit.value, agi: agi.value, luk: luk.value, terrain_defense: terrain.defense_bonus, terrain_evasion: terrain.evasion_bonus, status_defense: status_effects.defense_bonus(), },
+						hp: hp_current.value,
+						speed: agi.value,
+					},
+					&Combatant {
+						attack: AttackStats { str: target_str.value, dex: target_dex.value, luk: target_luk.value, },
+						defense: DefenseStats { vit: target_vit.value, agi: target_agi.value, luk: target_luk.value, terrain_defense: target_terrain.defense_bonus, terrain_evasion: target_terrain.evasion_bonus, status_defense: target_status_effects.defense_bonus(), },
+						hp: target_hp_current.value,
+						speed: target_agi.value,
+					},
+					angle,
+					can_counter,
+					&mut battle_rng.rng,
+				);
+				
+				// Apply the strikes and send them to the clients in order.
+				for (i, strike) in strikes.iter().enumerate() {
+					// Strikes that land apply the striker's attack status.
+					let hits = strike.outcome.result != AttackResult::Miss;
+					let (striker_pos, struck_pos, inflicted_status) = match strike.striker {
+						Side::Attacker => {
+							target_hp_current.value = strike.outcome.target_hp;
+							info!("DEBUG: Unit {} did {} damage to unit {} ({:?}).", unit_id.value, strike.outcome.damage, target_id.value, strike.outcome.result);
+							let inflicted_status = attack_status.status.filter(|_| hits);
+							if let Some(status) = inflicted_status {
+								target_status_effects.apply(status);
+							}
+							(*pos, *target_pos, inflicted_status)
+						},
+						Side::Defender => {
+							// The target turns to face the attacker to counter-attack.
+							target_dir.direction = direction_towards(target_pos, pos);
+							hp_current.value = strike.outcome.target_hp;
+							info!("DEBUG: Unit {} did {} damage to unit {} ({:?}).", target_id.value, strike.outcome.damage, unit_id.value, strike.outcome.result);
+							let inflicted_status = target_attack_status.status.filter(|_| hits);
+							if let Some(status) = inflicted_status {
+								status_effects.apply(status);
 							}
-						}
+							(*target_pos, *pos, inflicted_status)
+						},
+					};
+					
+					let message = ServerMessage::BasicAttack {
+						attacker: striker_pos,
+						target: struck_pos,
+						outcome: strike.outcome,
+						is_counterattack: strike.is_counterattack,
+						angle: strike.angle,
+						inflicted_status,
+					};
+					
+					// Send the first strike right away, and schedule each of the
+					// following ones 2 seconds after the previous one, so that
+					// the clients are able to show them one at a time.
+					if i == 0 {
+						info!("DEBUG: Sending `BasicAttack` message to clients.");
+						endpoint.broadcast_message(message).unwrap();
+						info!("DEBUG: Sent `BasicAttack` message to clients.");
+					} else {
+						combat_messages.messages.push((message, Timer::from_seconds(2.0, TimerMode::Once)));
 					}
-				},
-				_ => { empty_system() },
+				}
+				
+				info!("DEBUG: Unit {} now has {} HP.", unit_id.value, hp_current.value);
+				info!("DEBUG: Unit {} now has {} HP.", target_id.value, target_hp_current.value);
 			}
 			
+			// Remove Target marker component from the target.
+			commands.entity(target_entity).remove::<Target>();
+		} else {
+			info!("DEBUG: There is no unit on the target tile anymore.");
 		}
 		
 		// Remove Attacker marker component.
@@ -1483,66 +2226,191 @@ time: Res<Time>,
 	}	
 }
 
+// Prototype
+fn process_face_actions(
+mut commands: Commands,
+mut unit_query: Query<(Entity, &UnitId, &Pos, &mut UnitActions, &mut DIR, &FaceAction)>,
+mut server: ResMut<Server>,
+) {
+	let endpoint = server.endpoint_mut();
+	
+	for (entity, unit_id, pos, mut unit_actions, mut dir, face_action) in unit_query.iter_mut() {
+		info!("DEBUG: Processing Face action...");
+		
+		dir.direction = face_action.direction;
+		info!("DEBUG: Unit {} is now facing {:?}.", unit_id.value, dir.direction);
+		
+		// Send `Face` message to clients, now that the unit is done with its
+		// previous actions and stands where it turns.
+		endpoint.broadcast_message(ServerMessage::Face {
+			pos: *pos,
+			direction: dir.direction,
+		}).unwrap();
+		
+		// Remove Face UnitAction.
+		unit_actions.unit_actions.remove(0);
+		unit_actions.processing_unit_action = false;
+		commands.entity(entity).remove::<FaceAction>();
+		
+		info!("DEBUG: Processed Face action.");
+	}
+}
+
+// Prototype
+fn process_skill_actions(
+mut commands: Commands,
+grid: Res<BattleGrid>,
+skill_book: Res<SkillBook>,
+mut caster_query: Query<(Entity, &UnitId, &mut UnitActions, &Pos, &mut DIR, &mut MPCurrent, &INT, &MEN, &SkillAction)>,
+mut target_query: Query<(&UnitId, &mut HPCurrent, &HPMax, &MEN, &mut StatusEffects)>,
+mut server: ResMut<SessionServer>,
+) {
+	let endpoint = server.endpoint_mut();
+
+	for (entity, unit_id, mut unit_actions, pos, mut dir, mut mp_current, int, men, skill_action) in caster_query.iter_mut() {
+		info!("DEBUG: Processing UseSkill action...");
+		
+		// MP may have been spent since the skill was accepted, so the cast is
+		// cancelled if the caster can't pay for it anymore.
+		let skill = skill_book.get(&skill_action.skill_id).filter(|skill| {
+			let enough_mp = mp_current.value >= skill.mp_cost;
+			if !enough_mp {
+				info!("DEBUG: Unit {} doesn't have enough MP left for {}. Cancelling it.", unit_id.value, skill.name);
+			}
+			enough_mp
+		});
+		
+		if let Some(skill) = skill {
+			info!("DEBUG: Unit {} uses {}.", unit_id.value, skill.name);
+			
+			// Change caster's direction to face the target.
+			if skill_action.target != *pos {
+				dir.direction = direction_towards(pos, &skill_action.target);
+			}
+			
+			mp_current.value -= skill.mp_cost;
+			
+			// Resolve the skill on every unit in its area.
+			let caster = CasterStats { int: int.value, men: men.value, };
+			let mut outcomes = Vec::new();
+			for (target_pos, target_entity) in skill.area.units(&grid, *pos, skill_action.target) {
+				let Ok((target_id, mut target_hp_current, target_hp_max, target_men, mut target_status_effects)) = target_query.get_mut(target_entity) else {
+					continue;
+				};
+				
+				let terrain = &grid.get(target_pos).unwrap().terrain;
+				let outcome = skill.resolve(&caster, target_men.value, terrain, target_hp_current.value, target_hp_max.value);
+				target_hp_current.value = outcome.target_hp;
+				info!("DEBUG: Unit {} now has {} HP.", target_id.value, target_hp_current.value);
+				if let Some(status) = outcome.status {
+					target_status_effects.apply(status);
+					info!("DEBUG: Unit {} is now affected by {:?}.", target_id.value, status);
+				}
+				
+				outcomes.push((target_pos, outcome));
+			}
+			
+			info!("DEBUG: Sending `UseSkill` message to clients.");
+			endpoint.broadcast_message(ServerMessage::UseSkill {
+				caster: *pos,
+				skill_id: skill.id.clone(),
+				target: skill_action.target,
+				caster_mp: mp_current.value,
+				outcomes,
+			}).unwrap();
+			info!("DEBUG: Sent `UseSkill` message to clients.");
+		}
+		
+		// Remove UseSkill UnitAction.
+		unit_actions.unit_actions.remove(0);
+		unit_actions.processing_unit_action = false;
+		commands.entity(entity).remove::<SkillAction>();
+		
+		info!("DEBUG: Processed UseSkill action.");
+	}
+}
+
+// Prototype
+fn send_combat_messages(mut server: ResMut<SessionServer>, mut combat_messages: ResMut<CombatMessages>, time: Res<Time>) {
+	let messages = &mut combat_messages.messages;
+	if !messages.is_empty() && messages[0].1.tick(time.delta()).just_finished() {
+		let endpoint = server.endpoint_mut();
+		
+		let (message, _) = messages.remove(0);
+		info!("DEBUG: Sending `BasicAttack` message to clients.");
+		endpoint.broadcast_message(message).unwrap();
+		info!("DEBUG: Sent `BasicAttack` message to clients.");
+	}
+}
+
+// Prototype
+// Send the strikes that are still scheduled right away, so that they reach
+// the clients before a message that depends on them.
+fn flush_combat_messages(endpoint: &mut SessionEndpoint, combat_messages: &mut CombatMessages) {
+	for (message, _) in combat_messages.messages.drain(..) {
+		endpoint.broadcast_message(message).unwrap();
+	}
+}
+
 // Client
 fn spawn_units(
 mut commands: Commands,
-asset_server: Res<AssetServer>,
-mut map_query: Query<&mut Map>,
-tile_transform_query: Query<&Transform, With<GameText>>,
+grid: Option<ResMut<BattleGrid>>,
+skill_book: Option<Res<SkillBook>>,
 mut next_state: ResMut<NextState<GameState>>,
+lobby: Res<Lobby>,
+config: Res<ServerConfig>,
+mut events: EventWriter<BattleLoadFailedEvent>,
 ) {
-	info!("DEBUG: Starting to spawn units...");
+	// If the map or the skills couldn't be loaded, the battle is already
+	// being aborted.
+	let (Some(mut grid), Some(skill_book)) = (grid, skill_book) else {
+		return;
+	};
 
-	let mut map = &mut map_query.single_mut().map;
+	info!("DEBUG: Starting to spawn units...");
 
-	let mut rdr = Reader::from_path("src/the_patrol_ambush_data.csv").unwrap();
-	let mut records: Vec<StringRecord> = Vec::new();
-	for result in rdr.records(){
-		let record = result.unwrap();
-		//info!("{:?}", record);
-		records.push(record);
+	let records = match read_unit_records(&lobby.scenario.units_file) {
+		Ok(records) => records,
+		Err(error) => {
+			events.send(BattleLoadFailedEvent { reason: format!("{}: {}", lobby.scenario.units_file, error) });
+			return;
+		},
+	};
+	
+	// Parse every unit before spawning any of them.
+	let units = match records.iter().map(parse_unit_record).collect::<Result<Vec<UnitAttributes>, String>>() {
+		Ok(units) => units,
+		Err(reason) => {
+			events.send(BattleLoadFailedEvent { reason: format!("{}: {}", lobby.scenario.units_file, reason) });
+			return;
+		},
+	};
+	
+	// Check that every unit can stand where the battle places it.
+	if let Err(reason) = validate_unit_placements(&grid, &config.battle.teams, &units) {
+		events.send(BattleLoadFailedEvent { reason: format!("{}: {}", lobby.scenario.units_file, reason) });
+		return;
+	}
+	if let Err(reason) = validate_unit_skills(&skill_book, &units) {
+		events.send(BattleLoadFailedEvent { reason: format!("{}: {}", lobby.scenario.units_file, reason) });
+		return;
 	}
 	
-	for record in records {
+	for unit in units {
 		info!("DEBUG: Creating new unit...");
+		let pos = Pos { x: unit.pos_x.value, y: unit.pos_y.value, };
 		let entity_id = commands.spawn((
-			UnitAttributes {
-				unit_id : UnitId { value: record[0].parse().unwrap(), },
-				unit_team : UnitTeam { value: record[1].parse().unwrap(), },
-				unit_name : UnitName { value: record[2].to_string(), },
-				unit_class : UnitClass { value: record[3].to_string(), },
-				pos_x : PosX { value: record[4].parse().unwrap(), }, 
-				pos_y : PosY { value: record[5].parse().unwrap(), },
-				wt_max : WTMax { value: record[6].parse().unwrap(), },
-				wt_current : WTCurrent{ value: record[7].parse().unwrap(), },
-				hp_max : HPMax { value: record[8].parse().unwrap(), },
-				hp_current : HPCurrent { value: record[9].parse().unwrap(), },
-				mp_max : MPMax { value: record[10].parse().unwrap(), },
-				mp_current : MPCurrent { value: record[11].parse().unwrap(), },
-				str : STR { value: record[12].parse().unwrap(), },
-				vit : VIT { value: record[13].parse().unwrap(), },
-				int : INT { value: record[14].parse().unwrap(), },
-				men : MEN { value: record[15].parse().unwrap(), },
-				agi : AGI { value: record[16].parse().unwrap(), },
-				dex : DEX { value: record[17].parse().unwrap(), },
-				luk : LUK { value: record[18].parse().unwrap(), },
-				dir: DIR { direction: Direction::from_string(record[20].to_string()), },
-				movement_range: MovementRange { value: record[21].parse().unwrap(), },
-				attack_range: AttackRange { value: record[22].parse().unwrap(), },
-				attack_type: AttackType::from_string(record[23].to_string()),
-			},
+			unit,
 			Unit,
 			UnitActions { unit_actions: Default::default(), processing_unit_action: false, },
-			Pos {
-				x: record[4].parse().unwrap(),
-				y: record[5].parse().unwrap(),
-			},
+			pos,
 			MoveActions { move_actions: Vec::new(), },
+			WTProgress::default(),
+			TurnActions::default(),
 		)).id();
 		
-		let mut path_string: String = record[19].to_string();
-		path_string.push_str("_east.png");
-		map[record[4].parse::<usize>().unwrap()][record[5].parse::<usize>().unwrap()].2.push(entity_id);
+		grid.place(pos, entity_id);
 	}
 	
 	info!("DEBUG: Finished spawning units.");
@@ -1552,10 +2420,133 @@ mut next_state: ResMut<NextState<GameState>>,
 	info!("DEBUG: Set GameState to WaitTurn.");
 }
 
+// Server
+fn read_unit_records(path: &str) -> Result<Vec<StringRecord>, csv::Error> {
+	let mut rdr = Reader::from_path(path)?;
+	let mut records: Vec<StringRecord> = Vec::new();
+	for result in rdr.records(){
+		let record = result?;
+		//info!("{:?}", record);
+		records.push(record);
+	}
+	Ok(records)
+}
+
+// Server
+// Parse a unit of the battle CSV. Every column is checked, so that a file
+// in an older format is reported rather than crashing the server.
+fn parse_unit_record(record: &StringRecord) -> Result<UnitAttributes, String> {
+	let unit_id = unit_field(record, 0, "unit_id")?;
+	let direction = unit_field(record, 20, "DIR")?;
+	let attack_type = unit_field(record, 23, "AttackType")?;
+	let attack_status = match unit_field(record, 26, "AttackStatus")? {
+		"" => None,
+		status => Some(StatusKind::from_id(status).ok_or_else(|| format!("unit {} has an unknown attack status {:?}", unit_id, status))?),
+	};
+	let ai_profile = unit_field(record, 27, "AiProfile")?;
+	
+	Ok(UnitAttributes {
+		unit_id : UnitId { value: parse_unit_field(record, 0, "unit_id")?, },
+		unit_team : UnitTeam { value: parse_unit_field(record, 1, "unit_team")?, },
+		unit_name : UnitName { value: unit_field(record, 2, "unit_name")?.to_string(), },
+		unit_class : UnitClass { value: unit_field(record, 3, "unit_class")?.to_string(), },
+		pos_x : PosX { value: parse_unit_field(record, 4, "pos_x")?, },
+		pos_y : PosY { value: parse_unit_field(record, 5, "pos_y")?, },
+		wt_max : WTMax { value: parse_unit_field(record, 6, "WT_MAX")?, },
+		wt_current : WTCurrent{ value: parse_unit_field(record, 7, "WT_CURRENT")?, },
+		hp_max : HPMax { value: parse_unit_field(record, 8, "HP_MAX")?, },
+		hp_current : HPCurrent { value: parse_unit_field(record, 9, "HP_CURRENT")?, },
+		mp_max : MPMax { value: parse_unit_field(record, 10, "MP_MAX")?, },
+		mp_current : MPCurrent { value: parse_unit_field(record, 11, "MP_CURRENT")?, },
+		str : STR { value: parse_unit_field(record, 12, "STR")?, },
+		vit : VIT { value: parse_unit_field(record, 13, "VIT")?, },
+		int : INT { value: parse_unit_field(record, 14, "INT")?, },
+		men : MEN { value: parse_unit_field(record, 15, "MEN")?, },
+		agi : AGI { value: parse_unit_field(record, 16, "AGI")?, },
+		dex : DEX { value: parse_unit_field(record, 17, "DEX")?, },
+		luk : LUK { value: parse_unit_field(record, 18, "LUK")?, },
+		dir: DIR { direction: Direction::from_string(direction).ok_or_else(|| format!("unit {} has an invalid DIR {:?}", unit_id, direction))?, },
+		movement_range: MovementRange { value: parse_unit_field(record, 21, "MovementRange")?, },
+		attack_range: AttackRange { value: parse_unit_field(record, 22, "AttackRange")?, },
+		attack_type: AttackType::from_string(attack_type).ok_or_else(|| format!("unit {} has an invalid AttackType {:?}", unit_id, attack_type))?,
+		jump: Jump { value: parse_unit_field(record, 24, "Jump")?, },
+		skills: Skills { skill_ids: parse_skill_ids(unit_field(record, 25, "Skills")?), },
+		attack_status: AttackStatus { status: attack_status, },
+		status_effects: StatusEffects::default(),
+		ai_profile: AiProfile::from_string(ai_profile).map_err(|reason| format!("unit {} has an {}", unit_id, reason))?,
+	})
+}
+
+// Server
+// Column `column` of a unit of the battle CSV, called `name` in its header.
+fn unit_field<'a>(record: &'a StringRecord, column: usize, name: &str) -> Result<&'a str, String> {
+	record.get(column).ok_or_else(|| format!("unit {} has no {} column", record.get(0).unwrap_or("?"), name))
+}
+
+// Server
+fn parse_unit_field<T: std::str::FromStr>(record: &StringRecord, column: usize, name: &str) -> Result<T, String> {
+	let field = unit_field(record, column, name)?;
+	field.parse().map_err(|_| format!("unit {} has an invalid {} {:?}", record.get(0).unwrap_or("?"), name, field))
+}
+
+// Server
+fn validate_unit_placements(grid: &BattleGrid, teams: &[usize], units: &[UnitAttributes]) -> Result<(), String> {
+	let mut occupied_tiles = HashSet::new();
+	
+	for unit in units {
+		let unit_id = unit.unit_id.value;
+		let team = unit.unit_team.value;
+		let pos = Pos { x: unit.pos_x.value, y: unit.pos_y.value, };
+		
+		if !teams.contains(&team) {
+			return Err(format!("unit {} is in team {}, which is not a team of the battle", unit_id, team));
+		}
+		let Some(tile) = grid.get(pos) else {
+			return Err(format!("unit {} is outside the map at ({}, {})", unit_id, pos.x, pos.y));
+		};
+		if !grid.is_passable(pos) {
+			return Err(format!("unit {} is on an impassable tile at ({}, {})", unit_id, pos.x, pos.y));
+		}
+		if !occupied_tiles.insert(pos) {
+			return Err(format!("unit {} is on the same tile as another unit at ({}, {})", unit_id, pos.x, pos.y));
+		}
+		
+		// Units are expected to start in their team's spawn zone, if the map
+		// has one.
+		if grid.has_spawn_zone(team) && tile.flags.spawn_team != Some(team) {
+			warn!("Unit {} of team {} starts outside of its spawn zone at ({}, {}).", unit_id, team, pos.x, pos.y);
+		}
+	}
+	
+	Ok(())
+}
+
+// Server
+fn validate_unit_skills(skill_book: &SkillBook, units: &[UnitAttributes]) -> Result<(), String> {
+	for unit in units {
+		for skill_id in unit.skills.skill_ids.iter() {
+			if skill_book.get(skill_id).is_none() {
+				return Err(format!("unit {} has an unknown skill {:?}", unit.unit_id.value, skill_id));
+			}
+		}
+	}
+	
+	Ok(())
+}
+
+// Server
+// Skills are listed in the battle CSV separated by `;`.
+fn parse_skill_ids(skills: &str) -> Vec<String> {
+	skills.split(';')
+		.map(|skill_id| skill_id.trim().to_string())
+		.filter(|skill_id| !skill_id.is_empty())
+		.collect()
+}
+
 // Prototype
 fn handle_unit_death(
 mut commands: Commands,
-mut map_query: Query<&mut Map>,
+mut grid: ResMut<BattleGrid>,
 unit_query: Query<(Entity, &UnitId, &Pos, &HPCurrent)>,
 game: Res<Game>,
 mut next_state: ResMut<NextState<GameState>>,
@@ -1563,8 +2554,7 @@ mut next_state: ResMut<NextState<GameState>>,
 	for (entity, unit_id, pos, hp_current) in unit_query.iter() {
 		if hp_current.value == 0 {
 			// Remove unit.
-			let mut map = &mut map_query.single_mut().map;
-			map[pos.x][pos.y].2.remove(0);
+			grid.remove_occupant(*pos);
 			
 			info!("DEBUG: Unit {} has died. Removing it...", unit_id.value);
 			commands.entity(entity).despawn();
@@ -1582,54 +2572,44 @@ mut next_state: ResMut<NextState<GameState>>,
 
 // Prototype
 fn handle_game_over(
-unit_query: Query<(Entity, &UnitTeam)>,
+unit_query: Query<&UnitTeam>,
+slots: Res<TeamSlots>,
 mut next_state: ResMut<NextState<GameState>>,
 mut game: ResMut<Game>,
-mut server: ResMut<Server>,
+mut server: ResMut<SessionServer>,
+mut combat_messages: ResMut<CombatMessages>,
 ) {
-	let mut endpoint = server.endpoint_mut();
+	let endpoint = server.endpoint_mut();
 
-	let mut player_still_alive: bool = false;
-	let mut ai_still_alive: bool = false;
-	for (entity, unit_team) in unit_query.iter() {
-		if unit_team.value == 1 {
-			player_still_alive = true;
-		}
-		
-		if unit_team.value == 2 {
-			ai_still_alive = true;
+	// The battle goes on while at least two teams have units left.
+	let mut teams_alive: Vec<usize> = Vec::new();
+	for unit_team in unit_query.iter() {
+		if !teams_alive.contains(&unit_team.value) {
+			teams_alive.push(unit_team.value);
 		}
 	}
 	
-	if !player_still_alive {
-		// Player lost.
-		info!("DEBUG: Game over. Winner is AI.");
-		game.winner = ControlledBy::AI;
-		
-		// Send GameOver message.
-		endpoint.broadcast_message(ServerMessage::GameOver {
-			 winner: game.winner,
-		}).unwrap();
-		
-		info!("DEBUG: Setting GameState to MainMenu...");
-		next_state.set(GameState::MainMenu);
-		info!("DEBUG: Set GameState to MainMenu.");
+	if teams_alive.len() > 1 {
+		return;
 	}
 	
-	if !ai_still_alive {
-		// Player won.
-		info!("DEBUG: Game over. Winner is Player.");
-		game.winner = ControlledBy::Player;
-		
-		// Send GameOver message.
-		endpoint.broadcast_message(ServerMessage::GameOver {
-			 winner: game.winner,
-		}).unwrap();
-		
-		info!("DEBUG: Setting GameState to MainMenu...");
-		next_state.set(GameState::MainMenu);
-		info!("DEBUG: Set GameState to MainMenu.");
-	}
+	// Send the rest of the last combat exchange before the game ends.
+	flush_combat_messages(endpoint, &mut combat_messages);
+	
+	// The last team standing wins, if any.
+	game.winner = teams_alive.first().copied();
+	let winner_slot = game.winner.and_then(|team| slots.slots().iter().find(|slot| slot.team == team).copied());
+	info!("DEBUG: Game over. Winner is team {:?} ({:?}).", game.winner, winner_slot.map(|slot| slot.occupant));
+	
+	// Send GameOver message.
+	endpoint.broadcast_message(ServerMessage::GameOver {
+		winner: game.winner,
+		winner_slot,
+	}).unwrap();
+	
+	info!("DEBUG: Setting GameState to MainMenu...");
+	next_state.set(GameState::MainMenu);
+	info!("DEBUG: Set GameState to MainMenu.");
 }
 
 // Prototype
@@ -1640,218 +2620,100 @@ mut query: Query<Entity, Without<Window>>,
 	for entity in query.iter() {
 		commands.entity(entity).despawn();
 	}
+	commands.remove_resource::<BattleGrid>();
+	commands.remove_resource::<SkillBook>();
+	commands.insert_resource(TurnOrder::default());
 }
 
 // Utility
-fn find_path(map: Vec<Vec<(usize, TileType, Vec<Entity>, Vec<Entity>)>>, start: Pos, destination: Pos) -> Option<Vec<Pos>> {
-    // Define a heuristic function that estimates the distance between two positions.
-    // In this case, we use the Manhattan distance (taxicab distance).
-    let heuristic = |pos: &Pos| -> usize {
-        (pos.x as isize - destination.x as isize).abs() as usize
-            + (pos.y as isize - destination.y as isize).abs() as usize
-    };
-
-	// Use RefCell to wrap the map so that it can be mutated inside the closure.
-    let map_cell = RefCell::new(map);
-
-    // Define a function that returns the valid neighboring positions of a given position.
-    let neighbors = |pos: &Pos| -> Vec<(Pos, usize)> {
-		// Access the map using borrow_mut() to allow mutation.
-		let mut map = map_cell.borrow_mut();
-    
-        // Add logic to get the valid neighboring positions based on your map layout.
-        // For example, avoid diagonal moves and ensure the position is within the map bounds.
-        // For simplicity, let's assume you have a function called `get_valid_neighbors`.
-        get_valid_neighbors((&mut map).to_vec(), *pos)
-    };
-
-    // Use the `astar` function from the pathfinding library to find the path.
-    let result = astar(&start, neighbors, heuristic, |&pos| pos == destination);
-
-    if let Some((path, _)) = result {
-        Some(path)
-    } else {
-        None
-    }
+// Direction a unit at `from` has to face to look at `to`.
+fn direction_towards(from: &Pos, to: &Pos) -> Direction {
+	let mut direction = Direction::default();
+	if to.x < from.x {
+		direction = Direction::West;
+	} else if to.x > from.x {
+		direction = Direction::East;
+	}
+	if to.y < from.y {
+		direction = Direction::South;
+	} else if to.y > from.y {
+		direction = Direction::North;
+	}
+	direction
 }
 
 // Utility
-fn get_valid_neighbors(map: Vec<Vec<(usize, TileType, Vec<Entity>, Vec<Entity>)>>, pos: Pos) -> Vec<(Pos, usize)> {
-	let mut neighbors: Vec<(Pos, usize)> = Vec::new(); 
-	
-	// Check if tile is at North edge.
-	if pos.y == map[0].len() - 1 {
-		// Tile is at North edge. Don't add North neighbor.
-	} else {
-		// Tile is not at North edge. 
-		// Check if there's a unit on the tile.
-		// If there isn't, add North neighbor.
-		if map[pos.x][pos.y + 1].2.len() == 0 {
-			neighbors.push((Pos { x: pos.x, y: pos.y + 1, }, 0));
-		}
-	}
+// Side of a unit standing on `target_pos` and facing `target_direction` that
+// an attack from `attacker_pos` hits. Attacks coming in diagonally count as
+// coming from the front or the back.
+fn attack_angle(target_pos: &Pos, target_direction: &Direction, attacker_pos: &Pos) -> AttackAngle {
+	let (facing_x, facing_y) = target_direction.offset();
+	let delta_x = attacker_pos.x as isize - target_pos.x as isize;
+	let delta_y = attacker_pos.y as isize - target_pos.y as isize;
 	
-	// Check if tile is at South edge.
-	if pos.y == 0 {
-		// Tile is at South edge. Don't add South neighbor.
-	} else {
-		// Tile is not at South edge.
-		// Check if there's a unit on the tile.
-		// If there isn't, add South neighbor.
-		if map[pos.x][pos.y - 1].2.len() == 0 {
-			neighbors.push((Pos { x: pos.x, y: pos.y - 1, }, 0));
-		}
-	}
-	
-	// Check if tile is at East edge.
-	if pos.x == map.len() - 1 {
-		// Tile is at East edge. Don't add East neighbor.
-	} else {
-		// Tile is not at East edge.
-		// Check if there's a unit on the tile.
-		// If there isn't, add East neighbor.
-		if map[pos.x + 1][pos.y].2.len() == 0 {
-			neighbors.push((Pos { x: pos.x + 1, y: pos.y, }, 0));
-		}
-	} 
+	// Distance of the attacker ahead of the target, and to its side.
+	let ahead = delta_x * facing_x + delta_y * facing_y;
+	let aside = (delta_x * facing_y - delta_y * facing_x).abs();
 	
-	// Check if tile is at West edge.
-	if pos.x == 0 {
-		// Tile is at West edge. Don't add West neighbor.
+	if ahead > 0 && ahead >= aside {
+		AttackAngle::Front
+	} else if ahead < 0 && -ahead >= aside {
+		AttackAngle::Back
 	} else {
-		// Tile is not at West edge.
-		// Check if there's a unit on the tile.
-		// If there isn't, add West neighbor.
-		if map[pos.x - 1][pos.y].2.len() == 0 {
-			neighbors.push((Pos { x: pos.x - 1, y: pos.y, }, 0));
-		}
+		AttackAngle::Side
 	}
-	
-	return neighbors;
 }
 
-use std::collections::HashSet;
-
-// Prototype
-fn find_possible_movements(map: Vec<Vec<(usize, TileType, Vec<Entity>, Vec<Entity>)>>, start: Pos, mut movement_range: isize) -> Vec<Pos> {
-    let mut possible_tiles_vec = Vec::new();
-    movement_range -= 1;
-	
-	let mut visited_tiles = HashSet::new();
-	
-	if movement_range >= 0 {
-		// Get neighbors.
-		let neighbors = get_valid_neighbors(map.clone(), start);
-		for neighbor in &neighbors {
-			if visited_tiles.insert(neighbor.0) {
-				possible_tiles_vec.push(neighbor.0);
-				let mut recursive_possible_tiles = find_possible_movements(map.clone(), neighbor.0, movement_range);
-				
-				for possible_tile in recursive_possible_tiles {
-					if !possible_tiles_vec.contains(&possible_tile) {
-						possible_tiles_vec.push(possible_tile);
-					}
-				}
-			}
-		}
-    }
+// Utility
+// Clients are given the turns of the team in their slot (see
+// `wait_turn_system`).
+fn client_controls_team(slots: &TeamSlots, client_id: ClientId, team: usize) -> bool {
+	slots.client_of(team) == Some(client_id)
+}
 
-    possible_tiles_vec
+// Utility
+fn broadcast_lobby(endpoint: &mut SessionEndpoint, lobby: &LobbyContext) {
+	info!("DEBUG: Sending Lobby message...");
+	endpoint.broadcast_message(ServerMessage::Lobby {
+		players: lobby.lobby.players(&lobby.slots),
+		slots: lobby.slots.slots().to_vec(),
+		scenario: lobby.lobby.scenario.name.clone(),
+		scenarios: lobby.config.battle.scenario_names(),
+	}).unwrap();
+	info!("DEBUG: Sent Lobby message.");
 }
 
 // Utility
-fn get_valid_attack_neighbors(map: Vec<Vec<(usize, TileType, Vec<Entity>, Vec<Entity>)>>, pos: Pos) -> Vec<(Pos, usize)> {
-	let mut neighbors: Vec<(Pos, usize)> = Vec::new(); 
-	
-	// Check if tile is at North edge.
-	if pos.y == map[0].len() - 1 {
-		// Tile is at North edge. Don't add North neighbor.
-	} else {
-		// Tile is not at North edge. 
-		neighbors.push((Pos { x: pos.x, y: pos.y + 1, }, 0));
-	}
-	
-	// Check if tile is at South edge.
-	if pos.y == 0 {
-		// Tile is at South edge. Don't add South neighbor.
-	} else {
-		// Tile is not at South edge.
-		neighbors.push((Pos { x: pos.x, y: pos.y - 1, }, 0));
-	}
-	
-	// Check if tile is at East edge.
-	if pos.x == map.len() - 1 {
-		// Tile is at East edge. Don't add East neighbor.
-	} else {
-		// Tile is not at East edge.
-		neighbors.push((Pos { x: pos.x + 1, y: pos.y, }, 0));
-	} 
-	
-	// Check if tile is at West edge.
-	if pos.x == 0 {
-		// Tile is at West edge. Don't add West neighbor.
-	} else {
-		// Tile is not at West edge.
-		neighbors.push((Pos { x: pos.x - 1, y: pos.y, }, 0));
-	}
-	
-	return neighbors;
+fn broadcast_team_slots(endpoint: &mut SessionEndpoint, slots: &TeamSlots) {
+	info!("DEBUG: Sending TeamSlots message...");
+	endpoint.broadcast_message(ServerMessage::TeamSlots {
+		slots: slots.slots().to_vec(),
+	}).unwrap();
+	info!("DEBUG: Sent TeamSlots message.");
 }
 
+use std::collections::HashSet;
+
 // Prototype
-fn find_possible_attacks(map: Vec<Vec<(usize, TileType, Vec<Entity>, Vec<Entity>)>>, start: Pos, mut attack_range: isize, attack_type: AttackType) -> Vec<Pos> {
+fn find_possible_attacks(grid: &BattleGrid, start: Pos, attack_range: isize, attack_type: AttackType, units_block_line_of_sight: bool) -> Vec<Pos> {
     let mut possible_tiles_vec = Vec::new();
     
     match attack_type {
 		AttackType::Melee => {
-			// Get neighbors.
-			let neighbors = get_valid_attack_neighbors(map.clone(), start);
-			for neighbor in &neighbors {
-				possible_tiles_vec.push(neighbor.0);
-			}
-			
-			for neighbor in &neighbors {
-				for i in 1..attack_range {
-					// If there is an unit on the tile, don't search for more neighbors.
-					if map[neighbor.0.x][neighbor.0.y].2.len() > 0 {
+			// Melee attacks reach in straight lines from the unit.
+			let line = Shape::Line { length: attack_range.max(1) as usize };
+			for neighbor in grid.neighbors(start) {
+				for pos in line.area(grid, start, neighbor) {
+					possible_tiles_vec.push(pos);
+					// If there is an unit on the tile, the attack can't reach further.
+					if grid.is_occupied(pos) {
 						break;
 					}
-					// Else...
-					// Add next neighbor.
-					let neighbors = get_valid_attack_neighbors(map.clone(), neighbor.0);
-					for neighbor in neighbors {
-						if (neighbor.0.x == start.x || neighbor.0.y == start.y) {
-							if !(neighbor.0.x == start.x && neighbor.0.y == start.y) {
-								if !(possible_tiles_vec.contains(&neighbor.0)) {
-									possible_tiles_vec.push(neighbor.0);
-								}
-							}
-						}
-					}
-				}			
+				}
 			}
 		},
 		AttackType::Ranged => {
-			attack_range -= 1;
-			
-			let mut visited_tiles = HashSet::new();
-			
-			if attack_range >= 0 {
-				// Get neighbors.
-				let neighbors = get_valid_attack_neighbors(map.clone(), start);
-				for neighbor in &neighbors {
-					if visited_tiles.insert(neighbor.0) {
-						possible_tiles_vec.push(neighbor.0);
-						let mut recursive_possible_tiles = find_possible_attacks(map.clone(), neighbor.0, attack_range, attack_type);
-						
-						for possible_tile in recursive_possible_tiles {
-							if !possible_tiles_vec.contains(&possible_tile) {
-								possible_tiles_vec.push(possible_tile);
-							}
-						}
-					}
-				}
-			}
+			possible_tiles_vec = find_ranged_targets(grid, start, attack_range, units_block_line_of_sight).targetable;
 		},
 		_ => {
 			empty_system();
@@ -1863,6 +2725,275 @@ fn find_possible_attacks(map: Vec<Vec<(usize, TileType, Vec<Entity>, Vec<Entity>
     possible_tiles_vec
 }
 
+// Height difference that changes the reach of a ranged attack by one tile.
+const HEIGHT_PER_RANGE: isize = 2;
+
+// Utility
+// Reach of a ranged attack fired from `height_difference` above the target.
+// Every `HEIGHT_PER_RANGE` of height advantage adds a tile of reach, and
+// every `HEIGHT_PER_RANGE` of disadvantage takes one away.
+fn ranged_reach(attack_range: isize, height_difference: isize) -> isize {
+	attack_range + height_difference / HEIGHT_PER_RANGE
+}
+
+// Tiles within reach of a ranged attack, split by whether the shot is clear.
+struct RangedTargets {
+	targetable: Vec<Pos>,
+	obstructed: Vec<Pos>,
+}
+
+// Utility
+fn find_ranged_targets(grid: &BattleGrid, start: Pos, attack_range: isize, units_block_line_of_sight: bool) -> RangedTargets {
+	let mut ranged_targets = RangedTargets { targetable: Vec::new(), obstructed: Vec::new(), };
+	
+	let Some(start_height) = grid.height_at(start) else {
+		return ranged_targets;
+	};
+	
+	// Shooting down reaches further, shooting up reaches less far. No tile
+	// is lower than the ground, which bounds the farthest reach.
+	let farthest_reach = ranged_reach(attack_range, start_height as isize).max(0) as usize;
+	for pos in (Shape::Diamond { radius: farthest_reach }).reach(grid, start) {
+		let distance = pos.x.abs_diff(start.x) + pos.y.abs_diff(start.y);
+		let height_difference = start_height as isize - grid.height_at(pos).unwrap() as isize;
+		if distance > 0 && distance as isize <= ranged_reach(attack_range, height_difference) {
+			if grid.has_line_of_sight(start, pos, units_block_line_of_sight) {
+				ranged_targets.targetable.push(pos);
+			} else {
+				ranged_targets.obstructed.push(pos);
+			}
+		}
+	}
+	
+	ranged_targets
+}
+
 fn empty_system() {
 
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	use battle_map::{BattleMap, MapCell};
+
+	const UNIT: &str = "1,1,Hanno,Carthaginian Officer,1,1,600,600,60,60,30,30,60,60,60,60,60,60,50,hannibal,East,7,1,Melee,3,heal;fire,,";
+
+	fn record(line: &str) -> StringRecord {
+		StringRecord::from(line.split(',').collect::<Vec<&str>>())
+	}
+
+	// A unit of `UNIT`, with `column` set to `value`.
+	fn record_with(column: usize, value: &str) -> StringRecord {
+		let mut fields: Vec<&str> = UNIT.split(',').collect();
+		fields[column] = value;
+		StringRecord::from(fields)
+	}
+
+	fn unit(team: usize, x: usize, y: usize) -> UnitAttributes {
+		let mut unit = parse_unit_record(&record(UNIT)).ok().unwrap();
+		unit.unit_team.value = team;
+		unit.pos_x.value = x;
+		unit.pos_y.value = y;
+		unit
+	}
+
+	// A flat grass grid, with walls on the tiles of `walls`, a unit
+	// standing on each tile of `occupied`, and the first column in the spawn
+	// zone of team 1.
+	fn grid(width: usize, height: usize, walls: &[Pos], occupied: &[Pos]) -> BattleGrid {
+		let cells = (0..width)
+			.map(|x| (0..height)
+				.map(|y| MapCell {
+					height: 0,
+					tile_type: if walls.contains(&Pos { x, y }) { TileType::Wall } else { TileType::Grass },
+					passable: true,
+					spawn_team: if x == 0 { Some(1) } else { None },
+				})
+				.collect())
+			.collect();
+		let mut grid = BattleGrid::from_battle_map(&BattleMap { width, height, cells, });
+		for pos in occupied {
+			grid.place(*pos, Entity::from_raw(1));
+		}
+		grid
+	}
+
+	#[test]
+	fn parses_units() {
+		let unit = parse_unit_record(&record(UNIT)).ok().unwrap();
+
+		assert_eq!(unit.unit_name.value, "Hanno");
+		assert_eq!((unit.pos_x.value, unit.pos_y.value), (1, 1));
+		assert!(matches!(unit.dir.direction, Direction::East));
+		assert!(matches!(unit.attack_type, AttackType::Melee));
+		assert_eq!(unit.jump.value, 3);
+		assert_eq!(unit.skills.skill_ids, vec!["heal".to_string(), "fire".to_string()]);
+	}
+
+	#[test]
+	fn reports_missing_and_invalid_columns() {
+		let short = UNIT.rsplit_once(',').unwrap().0;
+		assert_eq!(parse_unit_record(&record(short)).err(), Some("unit 1 has no AiProfile column".to_string()));
+
+		assert_eq!(parse_unit_record(&record_with(24, "high")).err(), Some("unit 1 has an invalid Jump \"high\"".to_string()));
+		assert_eq!(parse_unit_record(&record_with(8, "-5")).err(), Some("unit 1 has an invalid HP_MAX \"-5\"".to_string()));
+		assert_eq!(parse_unit_record(&record_with(20, "Up")).err(), Some("unit 1 has an invalid DIR \"Up\"".to_string()));
+		assert_eq!(parse_unit_record(&record_with(23, "Magic")).err(), Some("unit 1 has an invalid AttackType \"Magic\"".to_string()));
+		assert_eq!(parse_unit_record(&record_with(26, "sleep")).err(), Some("unit 1 has an unknown attack status \"sleep\"".to_string()));
+		assert_eq!(parse_unit_record(&record_with(27, "berserk")).err(), Some("unit 1 has an unknown AI profile \"berserk\"".to_string()));
+	}
+
+	#[test]
+	fn parses_ai_profiles() {
+		assert_eq!(parse_unit_record(&record(UNIT)).ok().unwrap().ai_profile, AiProfile::Aggressive);
+		assert_eq!(parse_unit_record(&record_with(27, "guard:2:3")).ok().unwrap().ai_profile, AiProfile::Guard { tile: Pos { x: 2, y: 3 }, });
+	}
+
+	#[test]
+	fn parses_attack_statuses() {
+		assert_eq!(parse_unit_record(&record(UNIT)).ok().unwrap().attack_status.status, None);
+		assert_eq!(parse_unit_record(&record_with(26, "poison")).ok().unwrap().attack_status.status, Some(StatusKind::Poison));
+	}
+
+	#[test]
+	fn units_must_stand_on_free_passable_tiles_of_the_map() {
+		let grid = grid(3, 3, &[Pos { x: 2, y: 2 }], &[]);
+
+		assert_eq!(validate_unit_placements(&grid, &[1, 2], &[unit(1, 0, 0), unit(2, 1, 1)]), Ok(()));
+		assert!(validate_unit_placements(&grid, &[1, 2], &[unit(1, 0, 0), unit(2, 0, 0)]).is_err());
+		assert!(validate_unit_placements(&grid, &[1, 2], &[unit(1, 3, 0)]).is_err());
+		assert!(validate_unit_placements(&grid, &[1, 2], &[unit(1, 2, 2)]).is_err());
+	}
+
+	#[test]
+	fn units_must_belong_to_a_team_of_the_battle() {
+		let grid = grid(3, 3, &[], &[]);
+
+		assert_eq!(validate_unit_placements(&grid, &[1, 2], &[unit(3, 1, 1)]), Err("unit 1 is in team 3, which is not a team of the battle".to_string()));
+	}
+
+	#[test]
+	fn moves_start_from_the_unit_and_stay_in_range() {
+		let grid = grid(5, 5, &[], &[]);
+		let unit_pos = Pos { x: 0, y: 0 };
+		let jump = Jump { value: 1, };
+
+		assert_eq!(validate_move(&grid, &unit_pos, 2, &jump, unit_pos, Pos { x: 1, y: 1 }), Ok(()));
+		assert_eq!(validate_move(&grid, &unit_pos, 2, &jump, Pos { x: 1, y: 0 }, Pos { x: 1, y: 1 }), Err(ActionRejectedReason::InvalidOrigin));
+		assert_eq!(validate_move(&grid, &unit_pos, 2, &jump, unit_pos, Pos { x: 2, y: 1 }), Err(ActionRejectedReason::DestinationUnreachable));
+	}
+
+	#[test]
+	fn status_effects_change_movement_points() {
+		let mut status_effects = StatusEffects::default();
+		assert_eq!(movement_points(&MovementRange { value: 1, }, &status_effects), 1);
+
+		status_effects.apply(StatusKind::Haste);
+		assert_eq!(movement_points(&MovementRange { value: 1, }, &status_effects), 2);
+
+		let mut status_effects = StatusEffects::default();
+		status_effects.apply(StatusKind::Slow);
+		assert_eq!(movement_points(&MovementRange { value: 1, }, &status_effects), 0);
+		assert_eq!(movement_points(&MovementRange { value: 0, }, &status_effects), 0);
+	}
+
+	#[test]
+	fn units_block_the_way() {
+		let grid = grid(3, 1, &[], &[Pos { x: 1, y: 0 }]);
+		let unit_pos = Pos { x: 0, y: 0 };
+		let jump = Jump { value: 1, };
+
+		assert_eq!(validate_move(&grid, &unit_pos, 3, &jump, unit_pos, Pos { x: 1, y: 0 }), Err(ActionRejectedReason::DestinationUnreachable));
+		assert_eq!(validate_move(&grid, &unit_pos, 3, &jump, unit_pos, Pos { x: 2, y: 0 }), Err(ActionRejectedReason::DestinationUnreachable));
+	}
+
+	#[test]
+	fn height_changes_the_reach_of_ranged_attacks() {
+		assert_eq!(ranged_reach(4, 0), 4);
+		assert_eq!(ranged_reach(4, HEIGHT_PER_RANGE), 5);
+		assert_eq!(ranged_reach(4, 2 * HEIGHT_PER_RANGE + 1), 6);
+		assert_eq!(ranged_reach(4, -HEIGHT_PER_RANGE), 3);
+	}
+
+	#[test]
+	fn attacks_come_from_the_unit_and_hit_a_unit_in_range() {
+		let grid = grid(3, 3, &[], &[Pos { x: 1, y: 0 }, Pos { x: 2, y: 0 }]);
+		let unit_pos = Pos { x: 0, y: 0 };
+		let range = AttackRange { value: 1, };
+
+		assert_eq!(validate_basic_attack(&grid, &unit_pos, &range, &AttackType::Melee, unit_pos, Pos { x: 1, y: 0 }, false), Ok(Entity::from_raw(1)));
+		assert_eq!(validate_basic_attack(&grid, &unit_pos, &range, &AttackType::Melee, Pos { x: 1, y: 0 }, Pos { x: 2, y: 0 }, false), Err(ActionRejectedReason::InvalidAttacker));
+		assert_eq!(validate_basic_attack(&grid, &unit_pos, &range, &AttackType::Melee, unit_pos, Pos { x: 2, y: 0 }, false), Err(ActionRejectedReason::TargetOutOfRange));
+		assert_eq!(validate_basic_attack(&grid, &unit_pos, &range, &AttackType::Melee, unit_pos, Pos { x: 0, y: 1 }, false), Err(ActionRejectedReason::NoTargetUnit));
+	}
+
+	#[test]
+	fn ranged_attacks_need_a_clear_shot() {
+		let walled_grid = grid(5, 1, &[Pos { x: 1, y: 0 }], &[Pos { x: 2, y: 0 }, Pos { x: 3, y: 0 }]);
+		let unit_pos = Pos { x: 0, y: 0 };
+		let range = AttackRange { value: 4, };
+
+		assert_eq!(validate_basic_attack(&walled_grid, &unit_pos, &range, &AttackType::Ranged, unit_pos, Pos { x: 2, y: 0 }, false), Err(ActionRejectedReason::LineOfSightBlocked));
+
+		let open_grid = grid(5, 1, &[], &[Pos { x: 2, y: 0 }, Pos { x: 3, y: 0 }]);
+		assert_eq!(validate_basic_attack(&open_grid, &unit_pos, &range, &AttackType::Ranged, unit_pos, Pos { x: 3, y: 0 }, false), Ok(Entity::from_raw(1)));
+		assert_eq!(validate_basic_attack(&open_grid, &unit_pos, &range, &AttackType::Ranged, unit_pos, Pos { x: 3, y: 0 }, true), Err(ActionRejectedReason::LineOfSightBlocked));
+	}
+
+	#[test]
+	fn attacks_hit_the_side_of_the_target_they_come_from() {
+		let target_pos = Pos { x: 5, y: 5 };
+
+		assert_eq!(attack_angle(&target_pos, &Direction::East, &Pos { x: 6, y: 5 }), AttackAngle::Front);
+		assert_eq!(attack_angle(&target_pos, &Direction::East, &Pos { x: 4, y: 5 }), AttackAngle::Back);
+		assert_eq!(attack_angle(&target_pos, &Direction::East, &Pos { x: 5, y: 6 }), AttackAngle::Side);
+		assert_eq!(attack_angle(&target_pos, &Direction::North, &Pos { x: 5, y: 7 }), AttackAngle::Front);
+		assert_eq!(attack_angle(&target_pos, &Direction::South, &Pos { x: 5, y: 7 }), AttackAngle::Back);
+		assert_eq!(attack_angle(&target_pos, &Direction::West, &Pos { x: 5, y: 3 }), AttackAngle::Side);
+
+		// Diagonal attacks count as coming from the front or the back.
+		assert_eq!(attack_angle(&target_pos, &Direction::East, &Pos { x: 6, y: 6 }), AttackAngle::Front);
+		assert_eq!(attack_angle(&target_pos, &Direction::East, &Pos { x: 4, y: 4 }), AttackAngle::Back);
+		assert_eq!(attack_angle(&target_pos, &Direction::East, &Pos { x: 6, y: 8 }), AttackAngle::Side);
+	}
+
+	#[test]
+	fn units_only_know_skills_of_the_skill_file() {
+		let skill_book = SkillBook::from_file("src/skills.toml").unwrap();
+
+		assert_eq!(validate_unit_skills(&skill_book, &[unit(1, 0, 0)]), Ok(()));
+
+		let mut unit = unit(1, 0, 0);
+		unit.skills.skill_ids.push("meteor".to_string());
+		assert_eq!(validate_unit_skills(&skill_book, &[unit]), Err("unit 1 has an unknown skill \"meteor\"".to_string()));
+		assert_eq!(parse_skill_ids(""), Vec::<String>::new());
+	}
+
+	#[test]
+	fn skills_need_mp_and_a_unit_in_range() {
+		let grid = grid(6, 1, &[], &[Pos { x: 3, y: 0 }]);
+		let skill_book = SkillBook::from_file("src/skills.toml").unwrap();
+		let unit_pos = Pos { x: 0, y: 0 };
+		let skills = Skills { skill_ids: vec!["heal".to_string(), "fire".to_string()], };
+		let mp = |value| MPCurrent { value, };
+
+		assert_eq!(validate_skill(&grid, &skill_book, &unit_pos, &skills, &mp(8), "fire", Pos { x: 3, y: 0 }), Ok(()));
+		assert_eq!(validate_skill(&grid, &skill_book, &unit_pos, &skills, &mp(7), "fire", Pos { x: 3, y: 0 }), Err(ActionRejectedReason::NotEnoughMp));
+		assert_eq!(validate_skill(&grid, &skill_book, &unit_pos, &skills, &mp(50), "thunder", Pos { x: 3, y: 0 }), Err(ActionRejectedReason::UnknownSkill));
+		assert_eq!(validate_skill(&grid, &skill_book, &unit_pos, &skills, &mp(50), "meteor", Pos { x: 3, y: 0 }), Err(ActionRejectedReason::UnknownSkill));
+		assert_eq!(validate_skill(&grid, &skill_book, &unit_pos, &skills, &mp(50), "heal", Pos { x: 3, y: 0 }), Ok(()));
+		assert_eq!(validate_skill(&grid, &skill_book, &unit_pos, &skills, &mp(50), "heal", Pos { x: 4, y: 0 }), Err(ActionRejectedReason::TargetOutOfRange));
+		assert_eq!(validate_skill(&grid, &skill_book, &unit_pos, &skills, &mp(50), "fire", Pos { x: 5, y: 0 }), Err(ActionRejectedReason::NoTargetUnit));
+	}
+
+	#[test]
+	fn units_are_busy_until_their_actions_are_done() {
+		let mut unit_actions = UnitActions::default();
+		assert!(!unit_actions.is_busy());
+
+		unit_actions.processing_unit_action = true;
+		assert!(unit_actions.is_busy());
+	}
 }
\ No newline at end of file
@@ -0,0 +1,442 @@
+// (C) Copyright 2023 Ars Militaris Dev
+
+// Sessions.
+//
+// A server hosts many battles at once, each in its own session. A session is
+// an app of its own (see `session_app`), with its own world: its game, grid,
+// units, game state and clients belong to it alone, and a battle ending only
+// clears its own session.
+//
+// The server app owns the connections. Every frame, it hands each session the
+// messages of its clients, updates the sessions, and sends out the messages
+// they left in their `SessionServer`. Sessions read and send messages through
+// their `SessionServer` just as they would through the quinnet `Server`, and
+// are told of clients leaving them by a `ConnectionLostEvent`.
+//
+// Clients list the sessions with `ListSessions`, and pick one with
+// `CreateSession` or `JoinSession`. A client that sends any other message
+// before picking one joins the first session whose lobby is open, or a new
+// session if there is none. No more than `max_sessions` sessions are hosted
+// at once. Sessions are closed when their last client leaves, or when they
+// fail, in which case their clients are sent a `SessionClosed` message and
+// can pick another session.
+
+use bevy::prelude::*;
+
+use bevy_quinnet::{
+	server::{ConnectionLostEvent, Server},
+	shared::ClientId,
+};
+
+use serde::{Deserialize, Serialize};
+
+use std::any::Any;
+use std::collections::{HashMap, VecDeque};
+use std::panic::{self, AssertUnwindSafe};
+
+use crate::config::ServerConfig;
+use crate::{session_app, ActionRejectedReason, ClientMessage, GameState, ServerMessage};
+
+pub type SessionId = usize;
+
+// A session, as sent to the clients.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub struct SessionInfo {
+	pub session_id: SessionId,
+	pub clients: usize,
+	// Whether clients can join the session.
+	pub open: bool,
+}
+
+#[derive(Debug)]
+pub enum SessionError {
+	UnknownClient,
+}
+
+// The connections of a session, as seen from within the session.
+#[derive(Resource)]
+pub struct SessionServer {
+	session_id: SessionId,
+	endpoint: SessionEndpoint,
+}
+
+impl SessionServer {
+	pub fn new(session_id: SessionId) -> SessionServer {
+		SessionServer {
+			session_id,
+			endpoint: SessionEndpoint::default(),
+		}
+	}
+
+	pub fn session_id(&self) -> SessionId {
+		self.session_id
+	}
+
+	pub fn endpoint_mut(&mut self) -> &mut SessionEndpoint {
+		&mut self.endpoint
+	}
+}
+
+#[derive(Default)]
+pub struct SessionEndpoint {
+	clients: Vec<ClientId>,
+	// Messages received from each client, oldest first.
+	received: HashMap<ClientId, VecDeque<ClientMessage>>,
+	// Messages to send, with the clients to send them to.
+	outgoing: Vec<(Vec<ClientId>, ServerMessage)>,
+}
+
+impl SessionEndpoint {
+	pub fn clients(&self) -> Vec<ClientId> {
+		self.clients.clone()
+	}
+
+	pub fn receive_message_from(&mut self, client_id: ClientId) -> Result<Option<ClientMessage>, SessionError> {
+		if !self.clients.contains(&client_id) {
+			return Err(SessionError::UnknownClient);
+		}
+		Ok(self.received.get_mut(&client_id).and_then(|messages| messages.pop_front()))
+	}
+
+	pub fn send_message(&mut self, client_id: ClientId, message: ServerMessage) -> Result<(), SessionError> {
+		if !self.clients.contains(&client_id) {
+			return Err(SessionError::UnknownClient);
+		}
+		self.outgoing.push((vec![client_id], message));
+		Ok(())
+	}
+
+	// Send `message` to every client of the session.
+	pub fn broadcast_message(&mut self, message: ServerMessage) -> Result<(), SessionError> {
+		self.outgoing.push((self.clients.clone(), message));
+		Ok(())
+	}
+}
+
+struct Session {
+	id: SessionId,
+	app: App,
+}
+
+impl Session {
+	fn endpoint_mut(&mut self) -> &mut SessionEndpoint {
+		self.app.world.resource_mut::<SessionServer>().into_inner().endpoint_mut()
+	}
+
+	// Clients can join a session until its battle starts.
+	fn is_open(&self) -> bool {
+		matches!(self.app.world.resource::<State<GameState>>().get(), GameState::MainMenu | GameState::Lobby)
+	}
+
+	fn info(&self) -> SessionInfo {
+		SessionInfo {
+			session_id: self.id,
+			clients: self.app.world.resource::<SessionServer>().endpoint.clients.len(),
+			open: self.is_open(),
+		}
+	}
+}
+
+// The sessions of the server. Sessions are apps, which can't be shared
+// between threads, so this is a non-send resource.
+#[derive(Default)]
+pub struct Sessions {
+	sessions: Vec<Session>,
+	next_id: SessionId,
+	// Session of each client.
+	clients: HashMap<ClientId, SessionId>,
+}
+
+impl Sessions {
+	fn create(&mut self, config: &ServerConfig) -> Result<SessionId, ActionRejectedReason> {
+		if self.sessions.len() >= config.network.max_sessions {
+			return Err(ActionRejectedReason::TooManySessions);
+		}
+
+		let id = self.next_id;
+		self.next_id += 1;
+
+		let mut app = session_app(id, config.clone());
+		app.finish();
+		app.cleanup();
+		self.sessions.push(Session { id, app, });
+		info!("DEBUG: Created session {}.", id);
+		Ok(id)
+	}
+
+	// The first session clients can join, or a new session.
+	fn open_session(&mut self, config: &ServerConfig) -> Result<SessionId, ActionRejectedReason> {
+		match self.sessions.iter().find(|session| session.is_open()) {
+			Some(session) => Ok(session.id),
+			None => self.create(config),
+		}
+	}
+
+	fn join(&mut self, client_id: ClientId, session_id: SessionId) -> Result<(), ActionRejectedReason> {
+		let session = self.sessions.iter().find(|session| session.id == session_id).ok_or(ActionRejectedReason::UnknownSession)?;
+		if self.clients.get(&client_id) == Some(&session_id) {
+			return Ok(());
+		}
+		if !session.is_open() {
+			return Err(ActionRejectedReason::SessionInProgress);
+		}
+
+		self.leave(client_id);
+		let session = self.sessions.iter_mut().find(|session| session.id == session_id).unwrap();
+		session.endpoint_mut().clients.push(client_id);
+		self.clients.insert(client_id, session_id);
+		info!("DEBUG: Client {} joined session {}.", client_id, session_id);
+		Ok(())
+	}
+
+	fn leave(&mut self, client_id: ClientId) {
+		let Some(session_id) = self.clients.remove(&client_id) else {
+			return;
+		};
+		let Some(session) = self.sessions.iter_mut().find(|session| session.id == session_id) else {
+			return;
+		};
+
+		let endpoint = session.endpoint_mut();
+		endpoint.clients.retain(|client| *client != client_id);
+		endpoint.received.remove(&client_id);
+		session.app.world.send_event(ConnectionLostEvent { id: client_id, });
+		info!("DEBUG: Client {} left session {}.", client_id, session_id);
+	}
+
+	fn infos(&self) -> Vec<SessionInfo> {
+		self.sessions.iter().map(|session| session.info()).collect()
+	}
+
+	// Update every session, and return the messages they send, with the
+	// clients to send them to.
+	fn update(&mut self) -> Vec<(Vec<ClientId>, ServerMessage)> {
+		let mut outgoing = Vec::new();
+
+		let mut failed_sessions = Vec::new();
+		for session in self.sessions.iter_mut() {
+			// A session that panics is closed, without taking the other
+			// sessions down with it. Its clients can then pick another
+			// session.
+			if let Err(payload) = panic::catch_unwind(AssertUnwindSafe(|| session.app.update())) {
+				error!("Session {} failed: {}. Closing it.", session.id, panic_message(payload.as_ref()));
+				failed_sessions.push(session.id);
+				outgoing.push((session.endpoint_mut().clients.clone(), ServerMessage::SessionClosed { session_id: session.id, }));
+				continue;
+			}
+
+			outgoing.append(&mut session.endpoint_mut().outgoing);
+		}
+
+		self.clients.retain(|_, session_id| !failed_sessions.contains(session_id));
+		self.sessions.retain(|session| !failed_sessions.contains(&session.id));
+
+		// Close the sessions nobody is in anymore.
+		self.sessions.retain(|session| {
+			let empty = session.app.world.resource::<SessionServer>().endpoint.clients.is_empty();
+			if empty {
+				info!("DEBUG: Closed session {}.", session.id);
+			}
+			!empty
+		});
+
+		outgoing
+	}
+}
+
+pub struct SessionsPlugin;
+
+impl Plugin for SessionsPlugin {
+	fn build(&self, app: &mut App) {
+		app.init_non_send_resource::<Sessions>()
+			.add_systems(Update, (handle_connection_lost, route_client_messages, update_sessions).chain());
+	}
+}
+
+// Server
+fn handle_connection_lost(mut events: EventReader<ConnectionLostEvent>, mut sessions: NonSendMut<Sessions>) {
+	for event in events.iter() {
+		sessions.leave(event.id);
+	}
+}
+
+// Server
+fn route_client_messages(mut server: ResMut<Server>, mut sessions: NonSendMut<Sessions>, config: Res<ServerConfig>) {
+	let endpoint = server.endpoint_mut();
+
+	for client_id in endpoint.clients() {
+		while let Ok(Some(message)) = endpoint.receive_message_from::<ClientMessage>(client_id) {
+			let session_id = match message {
+				ClientMessage::ListSessions => {
+					endpoint.send_message(client_id, ServerMessage::Sessions { sessions: sessions.infos(), }).unwrap();
+					continue;
+				},
+				ClientMessage::CreateSession => {
+					match sessions.create(&config) {
+						Ok(session_id) => {
+							sessions.join(client_id, session_id).unwrap();
+							endpoint.send_message(client_id, ServerMessage::SessionJoined { session_id, }).unwrap();
+						},
+						Err(reason) => endpoint.send_message(client_id, ServerMessage::ActionRejected { reason, }).unwrap(),
+					}
+					continue;
+				},
+				ClientMessage::JoinSession { session_id } => {
+					match sessions.join(client_id, session_id) {
+						Ok(()) => endpoint.send_message(client_id, ServerMessage::SessionJoined { session_id, }).unwrap(),
+						Err(reason) => endpoint.send_message(client_id, ServerMessage::ActionRejected { reason, }).unwrap(),
+					}
+					continue;
+				},
+				_ => match sessions.clients.get(&client_id) {
+					Some(session_id) => *session_id,
+					None => {
+						let session_id = match sessions.open_session(&config) {
+							Ok(session_id) => session_id,
+							Err(reason) => {
+								endpoint.send_message(client_id, ServerMessage::ActionRejected { reason, }).unwrap();
+								continue;
+							},
+						};
+						sessions.join(client_id, session_id).unwrap();
+						endpoint.send_message(client_id, ServerMessage::SessionJoined { session_id, }).unwrap();
+						session_id
+					},
+				},
+			};
+
+			// Hand the message to the session of the client.
+			if let Some(session) = sessions.sessions.iter_mut().find(|session| session.id == session_id) {
+				session.endpoint_mut().received.entry(client_id).or_default().push_back(message);
+			}
+		}
+	}
+}
+
+// Server
+fn update_sessions(mut server: ResMut<Server>, mut sessions: NonSendMut<Sessions>) {
+	let endpoint = server.endpoint_mut();
+
+	// Clients may have left since the messages were sent, so failures are
+	// only logged.
+	for (clients, message) in sessions.update() {
+		endpoint.try_send_group_message(clients.iter(), message);
+	}
+}
+
+// Utility
+fn panic_message(payload: &(dyn Any + Send)) -> &str {
+	if let Some(message) = payload.downcast_ref::<&str>() {
+		message
+	} else if let Some(message) = payload.downcast_ref::<String>() {
+		message
+	} else {
+		"unknown error"
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn config(max_sessions: usize) -> ServerConfig {
+		let mut config = ServerConfig::default();
+		config.network.max_sessions = max_sessions;
+		config
+	}
+
+	fn start_battle(sessions: &mut Sessions, session_id: SessionId) {
+		let session = sessions.sessions.iter_mut().find(|session| session.id == session_id).unwrap();
+		session.app.world.insert_resource(State::new(GameState::Battle));
+	}
+
+	#[test]
+	fn caps_the_number_of_sessions() {
+		let config = config(2);
+		let mut sessions = Sessions::default();
+
+		assert_eq!(sessions.create(&config).unwrap(), 0);
+		assert_eq!(sessions.create(&config).unwrap(), 1);
+		assert!(matches!(sessions.create(&config), Err(ActionRejectedReason::TooManySessions)));
+		assert_eq!(sessions.sessions.len(), 2);
+	}
+
+	#[test]
+	fn clients_join_open_sessions() {
+		let config = config(2);
+		let mut sessions = Sessions::default();
+		let session_id = sessions.open_session(&config).unwrap();
+
+		assert_eq!(sessions.open_session(&config).unwrap(), session_id);
+		assert!(sessions.join(1, session_id).is_ok());
+		assert!(sessions.join(2, session_id).is_ok());
+		assert!(matches!(sessions.join(1, 5), Err(ActionRejectedReason::UnknownSession)));
+		assert_eq!(sessions.infos(), vec![SessionInfo { session_id, clients: 2, open: true, }]);
+
+		start_battle(&mut sessions, session_id);
+		assert!(matches!(sessions.join(3, session_id), Err(ActionRejectedReason::SessionInProgress)));
+		// Clients already in the session stay in it.
+		assert!(sessions.join(1, session_id).is_ok());
+		assert_eq!(sessions.infos(), vec![SessionInfo { session_id, clients: 2, open: false, }]);
+		assert_ne!(sessions.open_session(&config).unwrap(), session_id);
+	}
+
+	#[test]
+	fn clients_move_between_sessions() {
+		let config = config(2);
+		let mut sessions = Sessions::default();
+		let first = sessions.create(&config).unwrap();
+		let second = sessions.create(&config).unwrap();
+
+		sessions.join(1, first).unwrap();
+		sessions.join(1, second).unwrap();
+
+		assert_eq!(sessions.clients.get(&1), Some(&second));
+		assert_eq!(sessions.infos()[0].clients, 0);
+		assert_eq!(sessions.infos()[1].clients, 1);
+	}
+
+	#[test]
+	fn empty_sessions_are_closed() {
+		let config = config(2);
+		let mut sessions = Sessions::default();
+		let first = sessions.create(&config).unwrap();
+		let second = sessions.create(&config).unwrap();
+		sessions.join(1, first).unwrap();
+		sessions.join(2, second).unwrap();
+
+		sessions.leave(2);
+		sessions.update();
+
+		assert_eq!(sessions.infos().iter().map(|info| info.session_id).collect::<Vec<_>>(), vec![first]);
+		assert!(!sessions.clients.contains_key(&2));
+	}
+
+	#[test]
+	fn failed_sessions_are_closed() {
+		let config = config(2);
+		let mut sessions = Sessions::default();
+		let failing = sessions.create(&config).unwrap();
+		let other = sessions.create(&config).unwrap();
+		sessions.join(1, failing).unwrap();
+		sessions.join(2, failing).unwrap();
+		sessions.join(3, other).unwrap();
+		sessions.sessions[0].app.add_systems(Update, || panic!("boom"));
+
+		let outgoing = sessions.update();
+
+		assert!(outgoing.iter().any(|(clients, message)| clients == &vec![1, 2] && matches!(message, ServerMessage::SessionClosed { session_id } if *session_id == failing)));
+		assert_eq!(sessions.infos().iter().map(|info| info.session_id).collect::<Vec<_>>(), vec![other]);
+		assert!(!sessions.clients.contains_key(&1));
+		assert_eq!(sessions.clients.get(&3), Some(&other));
+		// Their clients can pick another session.
+		assert!(sessions.join(1, other).is_ok());
+	}
+
+	#[test]
+	fn reads_panic_messages() {
+		assert_eq!(panic_message(&"boom"), "boom");
+		assert_eq!(panic_message(&"boom".to_string()), "boom");
+		assert_eq!(panic_message(&1), "unknown error");
+	}
+}
@@ -0,0 +1,318 @@
+// (C) Copyright 2023 Ars Militaris Dev
+
+// Skills.
+//
+// Skills are defined in a TOML file, as a list of `[[skill]]` tables:
+//
+//   [[skill]]
+//   id = "fire"          # Identifier used by clients and the battle CSV.
+//   name = "Fire"
+//   mp_cost = 8          # MP spent by the caster.
+//   range = { shape = "diamond", radius = 4 }  # Tiles the caster can target.
+//   area = { shape = "cross", radius = 1 }     # Tiles hit around the target.
+//   effect = "damage"    # "damage", "heal" or "status".
+//   power = 20
+//   element = "fire"     # "none", "fire", "ice" or "lightning".
+//   caster_scaling = 50  # Percentage of the caster's INT (MEN for healing)
+//                        # added to `power`.
+//   target_scaling = 25  # Percentage of the target's MEN taken off the
+//                        # damage. Not used for healing.
+//   status = "stun"      # Status effect applied to the units hit.
+//
+// `range` and `area` are targeting shapes (see `targeting.rs`). `area`,
+// `element`, `caster_scaling`, `target_scaling` and `status` are optional.
+// `area` defaults to the targeted tile only, `element` to "none", and the
+// scalings to the values above. A skill with the "status" effect only applies
+// its status (see `status.rs`). The damage of an element against units
+// standing on some terrain is fixed by the rules of the game (see
+// `Element::terrain_bonus`).
+
+use bevy::prelude::*;
+
+use serde::{Deserialize, Serialize};
+
+use crate::TileType;
+use crate::status::StatusKind;
+use crate::targeting::Shape;
+
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::io;
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum SkillEffect {
+	Damage,
+	Heal,
+	Status,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum Element {
+	#[default]
+	None,
+	Fire,
+	Ice,
+	Lightning,
+}
+
+impl Element {
+	// Percentage of the damage dealt to a unit standing on `terrain`. Fire
+	// spreads in woods and lightning through water.
+	pub fn terrain_bonus(self, terrain: &TileType) -> usize {
+		match (self, terrain) {
+			(Element::Fire, TileType::Forest) => 150,
+			(Element::Fire, TileType::Water) => 50,
+			(Element::Lightning, TileType::Water) => 150,
+			_ => 100,
+		}
+	}
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct SkillDefinition {
+	pub id: String,
+	pub name: String,
+	pub mp_cost: usize,
+	pub range: Shape,
+	#[serde(default)]
+	pub area: Shape,
+	pub effect: SkillEffect,
+	pub power: usize,
+	#[serde(default)]
+	pub element: Element,
+	#[serde(default = "default_caster_scaling")]
+	pub caster_scaling: usize,
+	#[serde(default = "default_target_scaling")]
+	pub target_scaling: usize,
+	#[serde(default)]
+	pub status: Option<StatusKind>,
+}
+
+fn default_caster_scaling() -> usize {
+	50
+}
+
+fn default_target_scaling() -> usize {
+	25
+}
+
+// Caster stats that take part in a skill.
+pub struct CasterStats {
+	pub int: usize,
+	pub men: usize,
+}
+
+// Result of a skill on one of the units it hits, as sent to the clients.
+// `amount` is the damage done or the HP healed, depending on the skill.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy)]
+pub struct SkillOutcome {
+	pub amount: usize,
+	pub target_hp: usize,
+	pub status: Option<StatusKind>,
+}
+
+impl SkillDefinition {
+	// Resolve the skill against a unit with `target_hp` out of `target_hp_max`
+	// HP left, `target_men` MEN, standing on `terrain`.
+	pub fn resolve(&self, caster: &CasterStats, target_men: usize, terrain: &TileType, target_hp: usize, target_hp_max: usize) -> SkillOutcome {
+		match self.effect {
+			SkillEffect::Damage => {
+				let damage = (self.power + caster.int * self.caster_scaling / 100).saturating_sub(target_men * self.target_scaling / 100);
+				let damage = (damage * self.element.terrain_bonus(terrain) / 100).max(1);
+				SkillOutcome {
+					amount: damage,
+					target_hp: target_hp.saturating_sub(damage),
+					status: self.status,
+				}
+			},
+			SkillEffect::Heal => {
+				let heal = (self.power + caster.men * self.caster_scaling / 100).min(target_hp_max.saturating_sub(target_hp));
+				SkillOutcome {
+					amount: heal,
+					target_hp: target_hp + heal,
+					status: self.status,
+				}
+			},
+			SkillEffect::Status => SkillOutcome {
+				amount: 0,
+				target_hp,
+				status: self.status,
+			},
+		}
+	}
+}
+
+// Every skill of the battle, by id.
+#[derive(Resource, Debug, Clone, Default)]
+pub struct SkillBook {
+	skills: HashMap<String, SkillDefinition>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct SkillFile {
+	#[serde(default)]
+	skill: Vec<SkillDefinition>,
+}
+
+#[derive(Debug)]
+pub enum SkillError {
+	Io { path: String, error: io::Error },
+	Parse { path: String, error: toml::de::Error },
+	DuplicateSkill { path: String, id: String },
+}
+
+impl fmt::Display for SkillError {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match self {
+			SkillError::Io { path, error } => write!(f, "could not read skill file {}: {}", path, error),
+			SkillError::Parse { path, error } => write!(f, "invalid skill file {}: {}", path, error),
+			SkillError::DuplicateSkill { path, id } => write!(f, "{}: skill {:?} is defined more than once", path, id),
+		}
+	}
+}
+
+impl std::error::Error for SkillError {}
+
+impl SkillBook {
+	pub fn from_file(path: &str) -> Result<SkillBook, SkillError> {
+		let contents = fs::read_to_string(path).map_err(|error| SkillError::Io { path: path.to_string(), error })?;
+
+		SkillBook::parse(&contents, path)
+	}
+
+	// Read the skills of a skill file, `path` being only used in errors.
+	fn parse(contents: &str, path: &str) -> Result<SkillBook, SkillError> {
+		let skill_file: SkillFile = toml::from_str(contents).map_err(|error| SkillError::Parse { path: path.to_string(), error })?;
+
+		let mut skills = HashMap::new();
+		for skill in skill_file.skill {
+			if skills.contains_key(&skill.id) {
+				return Err(SkillError::DuplicateSkill { path: path.to_string(), id: skill.id });
+			}
+			skills.insert(skill.id.clone(), skill);
+		}
+
+		Ok(SkillBook { skills })
+	}
+
+	pub fn get(&self, id: &str) -> Option<&SkillDefinition> {
+		self.skills.get(id)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	const CASTER: CasterStats = CasterStats { int: 40, men: 20, };
+
+	fn skill(effect: SkillEffect, element: Element) -> SkillDefinition {
+		SkillDefinition {
+			id: "test".to_string(),
+			name: "Test".to_string(),
+			mp_cost: 5,
+			range: Shape::Diamond { radius: 3 },
+			area: Shape::Single,
+			effect,
+			power: 20,
+			element,
+			caster_scaling: default_caster_scaling(),
+			target_scaling: default_target_scaling(),
+			status: None,
+		}
+	}
+
+	#[test]
+	fn damage_scales_with_int_against_men() {
+		let fire = skill(SkillEffect::Damage, Element::None);
+
+		let outcome = fire.resolve(&CASTER, 40, &TileType::Grass, 100, 100);
+		assert_eq!((outcome.amount, outcome.target_hp), (30, 70));
+
+		let outcome = SkillDefinition { caster_scaling: 100, target_scaling: 0, ..fire.clone() }.resolve(&CASTER, 40, &TileType::Grass, 100, 100);
+		assert_eq!(outcome.amount, 60);
+
+		let outcome = fire.resolve(&CASTER, 40, &TileType::Grass, 10, 100);
+		assert_eq!(outcome.target_hp, 0);
+	}
+
+	#[test]
+	fn damaging_skills_always_do_some_damage() {
+		let outcome = skill(SkillEffect::Damage, Element::None).resolve(&CASTER, 1000, &TileType::Grass, 100, 100);
+
+		assert_eq!(outcome.amount, 1);
+	}
+
+	#[test]
+	fn healing_scales_with_men_up_to_max_hp() {
+		let heal = skill(SkillEffect::Heal, Element::None);
+
+		let outcome = heal.resolve(&CASTER, 40, &TileType::Grass, 50, 100);
+		assert_eq!((outcome.amount, outcome.target_hp), (30, 80));
+
+		let outcome = heal.resolve(&CASTER, 40, &TileType::Grass, 90, 100);
+		assert_eq!((outcome.amount, outcome.target_hp), (10, 100));
+	}
+
+	#[test]
+	fn skills_apply_their_status_to_the_target() {
+		let stun = SkillDefinition { effect: SkillEffect::Status, status: Some(StatusKind::Stun), ..skill(SkillEffect::Damage, Element::None) };
+		let outcome = stun.resolve(&CASTER, 40, &TileType::Grass, 60, 100);
+		assert_eq!((outcome.amount, outcome.target_hp, outcome.status), (0, 60, Some(StatusKind::Stun)));
+
+		let poison = SkillDefinition { status: Some(StatusKind::Poison), ..skill(SkillEffect::Damage, Element::None) };
+		assert_eq!(poison.resolve(&CASTER, 40, &TileType::Grass, 100, 100).status, Some(StatusKind::Poison));
+		assert_eq!(skill(SkillEffect::Heal, Element::None).resolve(&CASTER, 40, &TileType::Grass, 50, 100).status, None);
+	}
+
+	#[test]
+	fn elements_depend_on_the_terrain_of_the_target() {
+		assert_eq!(Element::Fire.terrain_bonus(&TileType::Forest), 150);
+		assert_eq!(Element::Fire.terrain_bonus(&TileType::Water), 50);
+		assert_eq!(Element::Lightning.terrain_bonus(&TileType::Water), 150);
+		assert_eq!(Element::Ice.terrain_bonus(&TileType::Forest), 100);
+		assert_eq!(Element::None.terrain_bonus(&TileType::Water), 100);
+
+		let fire = skill(SkillEffect::Damage, Element::Fire);
+		assert_eq!(fire.resolve(&CASTER, 40, &TileType::Forest, 100, 100).amount, 45);
+		assert_eq!(fire.resolve(&CASTER, 40, &TileType::Water, 100, 100).amount, 15);
+	}
+
+	#[test]
+	fn reads_skill_files() {
+		let skill_book = SkillBook::parse(include_str!("skills.toml"), "skills.toml").unwrap();
+
+		let fire = skill_book.get("fire").unwrap();
+		assert_eq!(fire.effect, SkillEffect::Damage);
+		assert_eq!(fire.element, Element::Fire);
+		assert_eq!((fire.caster_scaling, fire.target_scaling), (50, 25));
+		assert_eq!(fire.range, Shape::Diamond { radius: 5 });
+		assert_eq!(fire.area, Shape::Cross { radius: 1 });
+		assert_eq!(skill_book.get("heal").unwrap().element, Element::None);
+		assert_eq!(skill_book.get("heal").unwrap().area, Shape::Single);
+		assert!(skill_book.get("meteor").is_none());
+	}
+
+	#[test]
+	fn rejects_malformed_skill_files() {
+		let parse = |contents: &str| SkillBook::parse(contents, "skills.toml");
+
+		assert!(matches!(parse("[[skill]]\nid = \"fire\"\n"), Err(SkillError::Parse { .. })));
+		assert!(matches!(parse("[[skill]]\nid = \"fire\"\nname = \"Fire\"\nmp_cost = 8\nrange = { shape = \"diamond\", radius = 4 }\neffect = \"burn\"\npower = 20\n"), Err(SkillError::Parse { .. })));
+		assert!(matches!(parse("[[skill]]\nid = \"fire\"\nname = \"Fire\"\nmp_cost = -8\nrange = { shape = \"diamond\", radius = 4 }\neffect = \"damage\"\npower = 20\n"), Err(SkillError::Parse { .. })));
+		assert!(matches!(parse("[[skill]]\nid = \"fire\"\nname = \"Fire\"\nmp_cost = 8\nrange = { shape = \"diamond\", radius = 4 }\neffect = \"damage\"\npower = 20\ncolor = \"red\"\n"), Err(SkillError::Parse { .. })));
+
+		assert!(matches!(parse("[[skill]]\nid = \"fire\"\nname = \"Fire\"\nmp_cost = 8\nrange = { shape = \"ring\", radius = 4 }\neffect = \"damage\"\npower = 20\n"), Err(SkillError::Parse { .. })));
+		assert!(matches!(parse("[[skill]]\nid = \"fire\"\nname = \"Fire\"\nmp_cost = 8\nrange = { shape = \"line\" }\neffect = \"damage\"\npower = 20\n"), Err(SkillError::Parse { .. })));
+
+		let fire = "[[skill]]\nid = \"fire\"\nname = \"Fire\"\nmp_cost = 8\nrange = { shape = \"diamond\", radius = 4 }\neffect = \"damage\"\npower = 20\n";
+		assert!(parse(fire).is_ok());
+		assert!(matches!(parse(&format!("{}{}", fire, fire)), Err(SkillError::DuplicateSkill { id, .. }) if id == "fire"));
+		assert!(parse("").is_ok_and(|skill_book| skill_book.get("fire").is_none()));
+	}
+}
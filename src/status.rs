@@ -0,0 +1,226 @@
+// (C) Copyright 2023 Ars Militaris Dev
+
+// Status effects.
+//
+// Status effects are applied to units by skills and attacks. Each effect lasts
+// either a number of WT ticks, counted down every time the WT of the units
+// goes down, or a number of turns of the unit, counted down when its turn
+// starts. The status table below defines what each effect does and how long
+// it lasts.
+
+use bevy::prelude::*;
+
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum StatusKind {
+	// Loses HP at the start of each of its turns.
+	Poison,
+	// Loses its next turn.
+	Stun,
+	// Recovers WT faster and moves further.
+	Haste,
+	// Recovers WT slower and moves less far.
+	Slow,
+	// Takes less damage until its next turn.
+	Defend,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub enum StatusDuration {
+	Ticks(usize),
+	Turns(usize),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct StatusProperties {
+	pub duration: StatusDuration,
+	// Percentage of the normal WT recovery.
+	pub wt_recovery_percent: usize,
+	// Tiles added to, or taken from, the movement range.
+	pub movement_modifier: isize,
+	// Percentage of the damage taken that is absorbed.
+	pub defense_bonus: usize,
+	// Percentage of the max HP lost at the start of each turn.
+	pub turn_damage_percent: usize,
+	pub skips_turn: bool,
+}
+
+impl StatusKind {
+	// Parse a status as written in the battle CSV, e.g. "poison".
+	pub fn from_id(id: &str) -> Option<StatusKind> {
+		match id {
+			"poison" => Some(StatusKind::Poison),
+			"stun" => Some(StatusKind::Stun),
+			"haste" => Some(StatusKind::Haste),
+			"slow" => Some(StatusKind::Slow),
+			"defend" => Some(StatusKind::Defend),
+			_ => None,
+		}
+	}
+
+	pub fn properties(&self) -> StatusProperties {
+		let properties = StatusProperties {
+			duration: StatusDuration::Turns(1),
+			wt_recovery_percent: 100,
+			movement_modifier: 0,
+			defense_bonus: 0,
+			turn_damage_percent: 0,
+			skips_turn: false,
+		};
+		match self {
+			StatusKind::Poison => StatusProperties { duration: StatusDuration::Turns(3), turn_damage_percent: 10, ..properties },
+			StatusKind::Stun => StatusProperties { duration: StatusDuration::Turns(1), skips_turn: true, ..properties },
+			StatusKind::Haste => StatusProperties { duration: StatusDuration::Ticks(300), wt_recovery_percent: 200, movement_modifier: 1, ..properties },
+			StatusKind::Slow => StatusProperties { duration: StatusDuration::Ticks(300), wt_recovery_percent: 50, movement_modifier: -1, ..properties },
+			StatusKind::Defend => StatusProperties { duration: StatusDuration::Turns(1), defense_bonus: 50, ..properties },
+		}
+	}
+}
+
+// A status effect on a unit, as sent to the clients.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub struct StatusEffect {
+	pub kind: StatusKind,
+	pub remaining: StatusDuration,
+}
+
+// What happened to a unit's status effects at the start of its turn.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct TurnStart {
+	pub hp_lost: usize,
+	pub skips_turn: bool,
+}
+
+#[derive(Component, Debug, Clone, Default)]
+pub struct StatusEffects {
+	pub effects: Vec<StatusEffect>,
+}
+
+impl StatusEffects {
+	// Apply `kind` for its full duration. An effect that is already there
+	// starts over.
+	pub fn apply(&mut self, kind: StatusKind) {
+		self.effects.retain(|effect| effect.kind != kind);
+		self.effects.push(StatusEffect { kind, remaining: kind.properties().duration, });
+	}
+
+	pub fn movement_modifier(&self) -> isize {
+		self.effects.iter().map(|effect| effect.kind.properties().movement_modifier).sum()
+	}
+
+	pub fn defense_bonus(&self) -> usize {
+		self.effects.iter().map(|effect| effect.kind.properties().defense_bonus).sum::<usize>().min(100)
+	}
+
+	// Percentage of the unit's normal WT recovery.
+	pub fn wt_recovery_percent(&self) -> usize {
+		self.effects.iter().fold(100, |percent, effect| percent * effect.kind.properties().wt_recovery_percent / 100)
+	}
+
+	// Count a WT tick down.
+	pub fn tick(&mut self) {
+		for effect in self.effects.iter_mut() {
+			if let StatusDuration::Ticks(ticks) = &mut effect.remaining {
+				*ticks = ticks.saturating_sub(1);
+			}
+		}
+		self.effects.retain(|effect| effect.remaining != StatusDuration::Ticks(0));
+	}
+
+	// Count a turn of the unit down, as it starts. Effects that hurt the unit
+	// never take its last HP.
+	pub fn start_turn(&mut self, hp: &mut usize, hp_max: usize) -> TurnStart {
+		let mut turn_start = TurnStart::default();
+		for effect in self.effects.iter_mut() {
+			let properties = effect.kind.properties();
+			if properties.turn_damage_percent > 0 {
+				let damage = (hp_max * properties.turn_damage_percent / 100).max(1).min(hp.saturating_sub(1));
+				*hp -= damage;
+				turn_start.hp_lost += damage;
+			}
+			turn_start.skips_turn |= properties.skips_turn;
+
+			if let StatusDuration::Turns(turns) = &mut effect.remaining {
+				*turns = turns.saturating_sub(1);
+			}
+		}
+		self.effects.retain(|effect| effect.remaining != StatusDuration::Turns(0));
+
+		turn_start
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn applying_an_effect_again_starts_it_over() {
+		let mut status_effects = StatusEffects::default();
+		status_effects.apply(StatusKind::Haste);
+		status_effects.tick();
+		status_effects.apply(StatusKind::Haste);
+
+		assert_eq!(status_effects.effects, vec![StatusEffect { kind: StatusKind::Haste, remaining: StatusDuration::Ticks(300), }]);
+	}
+
+	#[test]
+	fn effects_change_speed_movement_and_defense() {
+		let mut status_effects = StatusEffects::default();
+		status_effects.apply(StatusKind::Haste);
+		status_effects.apply(StatusKind::Defend);
+
+		assert_eq!(status_effects.wt_recovery_percent(), 200);
+		assert_eq!(status_effects.movement_modifier(), 1);
+		assert_eq!(status_effects.defense_bonus(), 50);
+
+		status_effects.apply(StatusKind::Slow);
+		assert_eq!(status_effects.wt_recovery_percent(), 100);
+		assert_eq!(status_effects.movement_modifier(), 0);
+	}
+
+	#[test]
+	fn tick_effects_wear_off() {
+		let mut status_effects = StatusEffects::default();
+		status_effects.apply(StatusKind::Slow);
+		status_effects.apply(StatusKind::Poison);
+		for _ in 0..300 {
+			status_effects.tick();
+		}
+
+		assert_eq!(status_effects.effects.len(), 1);
+		assert_eq!(status_effects.effects[0].kind, StatusKind::Poison);
+	}
+
+	#[test]
+	fn poison_hurts_for_three_turns_but_never_kills() {
+		let mut status_effects = StatusEffects::default();
+		status_effects.apply(StatusKind::Poison);
+		let mut hp = 15;
+
+		assert_eq!(status_effects.start_turn(&mut hp, 100), TurnStart { hp_lost: 10, skips_turn: false, });
+		assert_eq!(status_effects.start_turn(&mut hp, 100), TurnStart { hp_lost: 4, skips_turn: false, });
+		assert_eq!(hp, 1);
+		assert_eq!(status_effects.start_turn(&mut hp, 100), TurnStart { hp_lost: 0, skips_turn: false, });
+		assert!(status_effects.effects.is_empty());
+	}
+
+	#[test]
+	fn stun_skips_one_turn() {
+		let mut status_effects = StatusEffects::default();
+		status_effects.apply(StatusKind::Stun);
+		let mut hp = 10;
+
+		assert!(status_effects.start_turn(&mut hp, 10).skips_turn);
+		assert!(!status_effects.start_turn(&mut hp, 10).skips_turn);
+	}
+
+	#[test]
+	fn parses_status_ids() {
+		assert_eq!(StatusKind::from_id("poison"), Some(StatusKind::Poison));
+		assert_eq!(StatusKind::from_id("defend"), Some(StatusKind::Defend));
+		assert_eq!(StatusKind::from_id("Poison"), None);
+	}
+}
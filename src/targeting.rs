@@ -0,0 +1,254 @@
+// (C) Copyright 2023 Ars Militaris Dev
+
+// Targeting shapes.
+//
+// A shape describes a set of tiles relative to the unit using it. Shapes are
+// used in two ways:
+//
+// - As a reach, centered on the unit, to find the tiles it can target.
+// - As an area, aimed at a targeted tile, to find the tiles it hits.
+//
+// `Single`, `Cross` and `Diamond` are centered on the targeted tile. `Line`
+// and `Cone` start next to the unit and point from the unit towards the
+// targeted tile; used as a reach, they point in all four directions.
+
+use bevy::prelude::*;
+
+use serde::Deserialize;
+
+use crate::{direction_towards, Direction, Pos};
+use crate::battle_grid::BattleGrid;
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Default)]
+#[serde(tag = "shape", rename_all = "snake_case", deny_unknown_fields)]
+pub enum Shape {
+	// The targeted tile only.
+	#[default]
+	Single,
+	// The targeted tile and the tiles up to `radius` steps away from it in a
+	// straight line.
+	Cross { radius: usize },
+	// The tiles up to `radius` steps away from the targeted tile.
+	Diamond { radius: usize },
+	// `length` tiles in a straight line.
+	Line { length: usize },
+	// A triangle `length` tiles deep, one tile wide next to the unit and two
+	// tiles wider at every step.
+	Cone { length: usize },
+}
+
+impl Shape {
+	// Tiles hit by the shape when a unit on `origin` aims it at `target`.
+	pub fn area(&self, grid: &BattleGrid, origin: Pos, target: Pos) -> Vec<Pos> {
+		let offsets = match self {
+			Shape::Single => vec![(0, 0)],
+			Shape::Cross { radius } => {
+				let radius = *radius as isize;
+				let mut offsets = vec![(0, 0)];
+				for step in 1..=radius {
+					offsets.extend([(0, step), (0, -step), (step, 0), (-step, 0)]);
+				}
+				offsets
+			},
+			Shape::Diamond { radius } => {
+				let radius = *radius as isize;
+				let mut offsets = Vec::new();
+				for x in -radius..=radius {
+					let height = radius - x.abs();
+					for y in -height..=height {
+						offsets.push((x, y));
+					}
+				}
+				offsets
+			},
+			Shape::Line { .. } | Shape::Cone { .. } => return self.aimed_area(grid, origin, direction_towards(&origin, &target)),
+		};
+
+		offsets.into_iter()
+			.filter_map(|offset| offset_pos(grid, target, offset))
+			.collect()
+	}
+
+	// Tiles a unit on `origin` can target with the shape.
+	pub fn reach(&self, grid: &BattleGrid, origin: Pos) -> Vec<Pos> {
+		match self {
+			Shape::Line { .. } | Shape::Cone { .. } => {
+				let mut tiles = Vec::new();
+				for direction in [Direction::North, Direction::South, Direction::East, Direction::West] {
+					for pos in self.aimed_area(grid, origin, direction) {
+						if !tiles.contains(&pos) {
+							tiles.push(pos);
+						}
+					}
+				}
+				tiles
+			},
+			_ => self.area(grid, origin, origin),
+		}
+	}
+
+	// The units standing on the tiles hit by the shape when a unit on
+	// `origin` aims it at `target`.
+	pub fn units(&self, grid: &BattleGrid, origin: Pos, target: Pos) -> Vec<(Pos, Entity)> {
+		self.area(grid, origin, target).into_iter()
+			.filter_map(|pos| grid.occupant(pos).map(|entity| (pos, entity)))
+			.collect()
+	}
+
+	// Tiles of a `Line` or a `Cone` starting next to `origin` and pointing
+	// to `direction`, nearest first.
+	fn aimed_area(&self, grid: &BattleGrid, origin: Pos, direction: Direction) -> Vec<Pos> {
+		let (forward_x, forward_y) = direction.offset();
+		let (side_x, side_y) = (forward_y, -forward_x);
+
+		let mut offsets = Vec::new();
+		match self {
+			Shape::Line { length } => {
+				for step in 1..=*length as isize {
+					offsets.push((forward_x * step, forward_y * step));
+				}
+			},
+			Shape::Cone { length } => {
+				for step in 1..=*length as isize {
+					for side in -(step - 1)..=(step - 1) {
+						offsets.push((forward_x * step + side_x * side, forward_y * step + side_y * side));
+					}
+				}
+			},
+			_ => {},
+		}
+
+		offsets.into_iter()
+			.filter_map(|offset| offset_pos(grid, origin, offset))
+			.collect()
+	}
+}
+
+// `pos` moved by `offset`, if it is still on the grid.
+fn offset_pos(grid: &BattleGrid, pos: Pos, (offset_x, offset_y): (isize, isize)) -> Option<Pos> {
+	let pos = Pos {
+		x: usize::try_from(pos.x as isize + offset_x).ok()?,
+		y: usize::try_from(pos.y as isize + offset_y).ok()?,
+	};
+	if grid.in_bounds(pos) {
+		Some(pos)
+	} else {
+		None
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	use crate::TileType;
+	use crate::battle_map::{BattleMap, MapCell};
+
+	// A flat 5x5 grass grid.
+	fn grid() -> BattleGrid {
+		let cells = (0..5)
+			.map(|_| (0..5)
+				.map(|_| MapCell { height: 0, tile_type: TileType::Grass, passable: true, spawn_team: None, })
+				.collect())
+			.collect();
+		BattleGrid::from_battle_map(&BattleMap { width: 5, height: 5, cells, })
+	}
+
+	// `tiles` as positions, in a stable order to compare them.
+	fn tiles(tiles: &[(usize, usize)]) -> Vec<Pos> {
+		sorted(tiles.iter().map(|(x, y)| Pos { x: *x, y: *y }).collect())
+	}
+
+	fn sorted(mut tiles: Vec<Pos>) -> Vec<Pos> {
+		tiles.sort_by_key(|pos| (pos.x, pos.y));
+		tiles
+	}
+
+	fn pos(x: usize, y: usize) -> Pos {
+		Pos { x, y }
+	}
+
+	#[test]
+	fn single_is_the_targeted_tile() {
+		let grid = grid();
+
+		assert_eq!(Shape::Single.area(&grid, pos(2, 2), pos(3, 4)), tiles(&[(3, 4)]));
+		assert_eq!(Shape::Single.reach(&grid, pos(2, 2)), tiles(&[(2, 2)]));
+		assert_eq!(Shape::Single.reach(&grid, pos(0, 0)), tiles(&[(0, 0)]));
+		assert_eq!(Shape::Single.area(&grid, pos(3, 3), pos(4, 4)), tiles(&[(4, 4)]));
+	}
+
+	#[test]
+	fn cross_spreads_in_straight_lines_from_the_targeted_tile() {
+		let grid = grid();
+		let cross = Shape::Cross { radius: 2 };
+
+		assert_eq!(sorted(cross.area(&grid, pos(0, 0), pos(2, 2))), tiles(&[(0, 2), (1, 2), (2, 0), (2, 1), (2, 2), (2, 3), (2, 4), (3, 2), (4, 2)]));
+		assert_eq!(sorted(cross.reach(&grid, pos(0, 0))), tiles(&[(0, 0), (0, 1), (0, 2), (1, 0), (2, 0)]));
+		assert_eq!(sorted(cross.area(&grid, pos(2, 2), pos(4, 4))), tiles(&[(2, 4), (3, 4), (4, 2), (4, 3), (4, 4)]));
+		assert_eq!(Shape::Cross { radius: 0 }.reach(&grid, pos(1, 1)), tiles(&[(1, 1)]));
+	}
+
+	#[test]
+	fn diamond_covers_the_tiles_near_the_targeted_tile() {
+		let grid = grid();
+		let diamond = Shape::Diamond { radius: 2 };
+
+		let area = diamond.area(&grid, pos(0, 0), pos(2, 2));
+		assert_eq!(area.len(), 13);
+		assert!(area.iter().all(|tile| tile.x.abs_diff(2) + tile.y.abs_diff(2) <= 2));
+		assert_eq!(sorted(diamond.reach(&grid, pos(0, 0))), tiles(&[(0, 0), (0, 1), (0, 2), (1, 0), (1, 1), (2, 0)]));
+		assert_eq!(sorted(diamond.area(&grid, pos(0, 0), pos(4, 2))), tiles(&[(2, 2), (3, 1), (3, 2), (3, 3), (4, 0), (4, 1), (4, 2), (4, 3), (4, 4)]));
+	}
+
+	#[test]
+	fn line_points_from_the_unit_towards_the_target() {
+		let grid = grid();
+		let line = Shape::Line { length: 2 };
+
+		assert_eq!(line.area(&grid, pos(2, 2), pos(4, 2)), vec![pos(3, 2), pos(4, 2)]);
+		assert_eq!(line.area(&grid, pos(2, 2), pos(0, 2)), vec![pos(1, 2), pos(0, 2)]);
+		assert_eq!(line.area(&grid, pos(2, 2), pos(2, 4)), vec![pos(2, 3), pos(2, 4)]);
+		assert_eq!(line.area(&grid, pos(2, 2), pos(2, 0)), vec![pos(2, 1), pos(2, 0)]);
+
+		// The line stops at the edge of the map.
+		assert_eq!(Shape::Line { length: 4 }.area(&grid, pos(2, 2), pos(3, 2)), vec![pos(3, 2), pos(4, 2)]);
+		assert_eq!(line.area(&grid, pos(1, 0), pos(0, 0)), vec![pos(0, 0)]);
+
+		assert_eq!(sorted(Shape::Line { length: 1 }.reach(&grid, pos(2, 2))), tiles(&[(1, 2), (2, 1), (2, 3), (3, 2)]));
+		assert_eq!(sorted(line.reach(&grid, pos(0, 0))), tiles(&[(0, 1), (0, 2), (1, 0), (2, 0)]));
+	}
+
+	#[test]
+	fn cone_widens_away_from_the_unit() {
+		let grid = grid();
+		let cone = Shape::Cone { length: 2 };
+
+		assert_eq!(sorted(cone.area(&grid, pos(2, 2), pos(3, 2))), tiles(&[(3, 2), (4, 1), (4, 2), (4, 3)]));
+		assert_eq!(sorted(cone.area(&grid, pos(2, 2), pos(1, 2))), tiles(&[(1, 2), (0, 1), (0, 2), (0, 3)]));
+		assert_eq!(sorted(cone.area(&grid, pos(2, 2), pos(2, 3))), tiles(&[(2, 3), (1, 4), (2, 4), (3, 4)]));
+		assert_eq!(sorted(cone.area(&grid, pos(2, 2), pos(2, 1))), tiles(&[(2, 1), (1, 0), (2, 0), (3, 0)]));
+
+		// The cone is cut by the edges of the map.
+		assert_eq!(sorted(cone.area(&grid, pos(3, 2), pos(4, 2))), tiles(&[(4, 2)]));
+		assert_eq!(sorted(cone.area(&grid, pos(0, 1), pos(1, 1))), tiles(&[(1, 1), (2, 0), (2, 1), (2, 2)]));
+
+		assert_eq!(cone.reach(&grid, pos(2, 2)).len(), 16);
+		assert_eq!(sorted(cone.reach(&grid, pos(0, 0))), tiles(&[(0, 1), (0, 2), (1, 0), (1, 2), (2, 0), (2, 1)]));
+	}
+
+	#[test]
+	fn units_are_the_occupants_of_the_area() {
+		let mut grid = grid();
+		grid.place(pos(2, 3), Entity::from_raw(1));
+		grid.place(pos(2, 4), Entity::from_raw(2));
+		grid.place(pos(4, 4), Entity::from_raw(3));
+
+		assert_eq!(Shape::Single.units(&grid, pos(2, 2), pos(2, 3)), vec![(pos(2, 3), Entity::from_raw(1))]);
+		assert!(Shape::Single.units(&grid, pos(2, 2), pos(3, 3)).is_empty());
+		assert_eq!(Shape::Cross { radius: 1 }.units(&grid, pos(0, 0), pos(4, 3)), vec![(pos(4, 4), Entity::from_raw(3))]);
+		assert_eq!(Shape::Line { length: 3 }.units(&grid, pos(2, 2), pos(2, 3)), vec![(pos(2, 3), Entity::from_raw(1)), (pos(2, 4), Entity::from_raw(2))]);
+		assert!(Shape::Line { length: 3 }.units(&grid, pos(2, 2), pos(2, 1)).is_empty());
+		assert_eq!(Shape::Cone { length: 2 }.units(&grid, pos(3, 2), pos(3, 3)), vec![(pos(2, 4), Entity::from_raw(2)), (pos(4, 4), Entity::from_raw(3))]);
+	}
+}
@@ -0,0 +1,199 @@
+// (C) Copyright 2023 Ars Militaris Dev
+
+// Team slots.
+//
+// Every team of the battle has a slot, which is either open, taken by a
+// client, or played by the AI. Clients get the first open slot when they
+// join, and can then move to another open slot. When the battle starts, the
+// slots still open are filled with the AI, and so are the slots of clients
+// that leave during the battle.
+//
+// Turns of a team go to the client in its slot, whatever the order clients
+// connected in.
+
+use bevy::prelude::*;
+
+use bevy_quinnet::shared::ClientId;
+
+use serde::{Deserialize, Serialize};
+
+use crate::ControlledBy;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub enum SlotOccupant {
+	Open,
+	Client(ClientId),
+	AI,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub struct TeamSlot {
+	pub team: usize,
+	pub occupant: SlotOccupant,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub enum SlotError {
+	UnknownTeam,
+	SlotTaken,
+}
+
+#[derive(Resource, Debug, Clone)]
+pub struct TeamSlots {
+	slots: Vec<TeamSlot>,
+	// Teams always played by the AI.
+	ai_teams: Vec<usize>,
+}
+
+impl TeamSlots {
+	pub fn new(teams: &[usize], ai_teams: &[usize]) -> TeamSlots {
+		let mut team_slots = TeamSlots {
+			slots: teams.iter().map(|team| TeamSlot { team: *team, occupant: SlotOccupant::Open, }).collect(),
+			ai_teams: ai_teams.to_vec(),
+		};
+		team_slots.reset();
+		team_slots
+	}
+
+	pub fn slots(&self) -> &[TeamSlot] {
+		&self.slots
+	}
+
+	// Open the slots filled with the AI for the last battle again, keeping
+	// the clients in their slots.
+	pub fn reset(&mut self) {
+		for slot in self.slots.iter_mut() {
+			if self.ai_teams.contains(&slot.team) {
+				slot.occupant = SlotOccupant::AI;
+			} else if slot.occupant == SlotOccupant::AI {
+				slot.occupant = SlotOccupant::Open;
+			}
+		}
+	}
+
+	// Give `client_id` the first open slot, unless it already has one.
+	pub fn join(&mut self, client_id: ClientId) -> Option<usize> {
+		if let Some(team) = self.team_of(client_id) {
+			return Some(team);
+		}
+		let slot = self.slots.iter_mut().find(|slot| slot.occupant == SlotOccupant::Open)?;
+		slot.occupant = SlotOccupant::Client(client_id);
+		Some(slot.team)
+	}
+
+	// Move `client_id` to the slot of `team`.
+	pub fn choose_team(&mut self, client_id: ClientId, team: usize) -> Result<(), SlotError> {
+		let slot = self.slots.iter().find(|slot| slot.team == team).ok_or(SlotError::UnknownTeam)?;
+		match slot.occupant {
+			SlotOccupant::Client(occupant) if occupant == client_id => return Ok(()),
+			SlotOccupant::Open => {},
+			_ => return Err(SlotError::SlotTaken),
+		}
+
+		self.leave(client_id);
+		for slot in self.slots.iter_mut().filter(|slot| slot.team == team) {
+			slot.occupant = SlotOccupant::Client(client_id);
+		}
+		Ok(())
+	}
+
+	// Open the slot of `client_id`, and return its team.
+	pub fn leave(&mut self, client_id: ClientId) -> Option<usize> {
+		let slot = self.slots.iter_mut().find(|slot| slot.occupant == SlotOccupant::Client(client_id))?;
+		slot.occupant = SlotOccupant::Open;
+		Some(slot.team)
+	}
+
+	// Let the AI play the teams nobody plays.
+	pub fn fill_with_ai(&mut self) {
+		for slot in self.slots.iter_mut().filter(|slot| slot.occupant == SlotOccupant::Open) {
+			slot.occupant = SlotOccupant::AI;
+		}
+	}
+
+	pub fn team_of(&self, client_id: ClientId) -> Option<usize> {
+		self.slots.iter()
+			.find(|slot| slot.occupant == SlotOccupant::Client(client_id))
+			.map(|slot| slot.team)
+	}
+
+	pub fn client_of(&self, team: usize) -> Option<ClientId> {
+		self.slots.iter()
+			.find(|slot| slot.team == team)
+			.and_then(|slot| match slot.occupant {
+				SlotOccupant::Client(client_id) => Some(client_id),
+				_ => None,
+			})
+	}
+
+	pub fn controller(&self, team: usize) -> ControlledBy {
+		match self.slots.iter().find(|slot| slot.team == team).map(|slot| slot.occupant) {
+			Some(SlotOccupant::Client(_)) => ControlledBy::Player,
+			Some(SlotOccupant::AI) => ControlledBy::AI,
+			Some(SlotOccupant::Open) | None => ControlledBy::None,
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn occupants(slots: &TeamSlots) -> Vec<SlotOccupant> {
+		slots.slots().iter().map(|slot| slot.occupant).collect()
+	}
+
+	#[test]
+	fn clients_take_the_first_open_slot() {
+		let mut slots = TeamSlots::new(&[1, 2, 3], &[2]);
+
+		assert_eq!(slots.join(10), Some(1));
+		assert_eq!(slots.join(10), Some(1));
+		assert_eq!(slots.join(11), Some(3));
+		assert_eq!(slots.join(12), None);
+		assert_eq!(occupants(&slots), vec![SlotOccupant::Client(10), SlotOccupant::AI, SlotOccupant::Client(11)]);
+	}
+
+	#[test]
+	fn clients_move_to_open_slots_only() {
+		let mut slots = TeamSlots::new(&[1, 2, 3], &[3]);
+		slots.join(10);
+		slots.join(11);
+
+		assert_eq!(slots.choose_team(10, 2), Err(SlotError::SlotTaken));
+		assert_eq!(slots.choose_team(10, 3), Err(SlotError::SlotTaken));
+		assert_eq!(slots.choose_team(10, 4), Err(SlotError::UnknownTeam));
+		assert_eq!(slots.choose_team(10, 1), Ok(()));
+
+		slots.leave(11);
+		assert_eq!(slots.choose_team(10, 2), Ok(()));
+		assert_eq!(slots.team_of(10), Some(2));
+		assert_eq!(slots.controller(1), ControlledBy::None);
+	}
+
+	#[test]
+	fn turns_go_to_the_client_in_the_slot() {
+		let mut slots = TeamSlots::new(&[1, 2], &[]);
+		slots.join(10);
+		slots.join(11);
+		slots.leave(10);
+		slots.choose_team(11, 1).unwrap();
+
+		assert_eq!(slots.client_of(1), Some(11));
+		assert_eq!(slots.client_of(2), None);
+		assert_eq!(slots.controller(1), ControlledBy::Player);
+	}
+
+	#[test]
+	fn the_ai_fills_open_slots_until_reset() {
+		let mut slots = TeamSlots::new(&[1, 2, 3], &[3]);
+		slots.join(10);
+		slots.fill_with_ai();
+
+		assert_eq!(slots.controller(2), ControlledBy::AI);
+		assert_eq!(slots.join(11), None);
+
+		slots.reset();
+		assert_eq!(occupants(&slots), vec![SlotOccupant::Client(10), SlotOccupant::Open, SlotOccupant::AI]);
+	}
+}
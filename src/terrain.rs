@@ -0,0 +1,36 @@
+// (C) Copyright 2023 Ars Militaris Dev
+
+// Terrain table.
+//
+// Every terrain has a movement cost, paid when a unit steps onto a tile of
+// that terrain, and may be impassable. Units standing on a tile also get the
+// defense and evasion bonuses of its terrain when they are attacked. Tall
+// terrain, like trees and walls, rises above the height of its tile and can
+// block ranged attacks.
+
+use crate::TileType;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TerrainProperties {
+	// Movement points spent to step onto the tile.
+	pub movement_cost: usize,
+	pub passable: bool,
+	// Percentage of the damage absorbed by the terrain.
+	pub defense_bonus: usize,
+	// Percentage added to the chance of avoiding an attack.
+	pub evasion_bonus: usize,
+	// How far the terrain rises above the height of its tile.
+	pub sight_height: usize,
+}
+
+impl TileType {
+	pub fn properties(&self) -> TerrainProperties {
+		match self {
+			TileType::Grass => TerrainProperties { movement_cost: 1, passable: true, defense_bonus: 0, evasion_bonus: 0, sight_height: 0, },
+			TileType::Road => TerrainProperties { movement_cost: 1, passable: true, defense_bonus: 0, evasion_bonus: 0, sight_height: 0, },
+			TileType::Forest => TerrainProperties { movement_cost: 2, passable: true, defense_bonus: 10, evasion_bonus: 20, sight_height: 1, },
+			TileType::Water => TerrainProperties { movement_cost: 3, passable: true, defense_bonus: 0, evasion_bonus: 0, sight_height: 0, },
+			TileType::Wall => TerrainProperties { movement_cost: 0, passable: false, defense_bonus: 0, evasion_bonus: 0, sight_height: 3, },
+		}
+	}
+}
@@ -0,0 +1,208 @@
+// (C) Copyright 2023 Ars Militaris Dev
+
+// Turn order.
+//
+// Units take turns when their WT reaches 0. Every WT tick, each unit recovers
+// WT at a speed set by its AGI and changed by its status effects: a unit with
+// `BASE_SPEED` AGI and no status effects recovers 1 WT per tick. When several
+// units reach 0 WT on the same tick, the one with the lowest unit id goes
+// first.
+//
+// After its turn, a unit waits again for part of its max WT, depending on
+// what it did during the turn: moving and acting make it wait longer.
+
+use bevy::prelude::*;
+
+// AGI of a unit that recovers 1 WT per tick.
+pub const BASE_SPEED: usize = 60;
+
+// Percentages of the max WT a unit waits after its turn.
+pub const WAIT_WT_PERCENT: usize = 60;
+pub const MOVE_WT_PERCENT: usize = 20;
+pub const ACT_WT_PERCENT: usize = 20;
+
+// Number of upcoming turns sent to the clients.
+pub const FORECAST_TURNS: usize = 10;
+
+// What a unit has done during its turn.
+#[derive(Component, Debug, Clone, Copy, Default)]
+pub struct TurnActions {
+	pub moved: bool,
+	pub acted: bool,
+}
+
+impl TurnActions {
+	// WT the unit waits for after its turn.
+	pub fn wt_cost(&self, wt_max: usize) -> usize {
+		let mut percent = WAIT_WT_PERCENT;
+		if self.moved {
+			percent += MOVE_WT_PERCENT;
+		}
+		if self.acted {
+			percent += ACT_WT_PERCENT;
+		}
+		wt_max * percent / 100
+	}
+}
+
+// Percentage of the base WT recovery of a unit with `agi` AGI, whose status
+// effects recover WT at `status_percent` of the normal speed.
+pub fn recovery_percent(agi: usize, status_percent: usize) -> usize {
+	agi * status_percent / BASE_SPEED
+}
+
+// WT recovered by a unit, in hundredths of WT, so that units recover WT at
+// any speed.
+#[derive(Component, Debug, Clone, Copy, Default)]
+pub struct WTProgress {
+	hundredths: usize,
+}
+
+impl WTProgress {
+	// Recover WT for a tick at `recovery_percent` of the base speed, and
+	// return what is left of `wt_current`.
+	pub fn recover(&mut self, wt_current: usize, recovery_percent: usize) -> usize {
+		self.recover_for(wt_current, recovery_percent, 1)
+	}
+
+	// Recover WT for `ticks` ticks at once.
+	pub fn recover_for(&mut self, wt_current: usize, recovery_percent: usize, ticks: usize) -> usize {
+		self.hundredths += recovery_percent * ticks;
+		let recovered = self.hundredths / 100;
+		self.hundredths %= 100;
+		wt_current.saturating_sub(recovered)
+	}
+
+	// Ticks until `wt_current` reaches 0, if it ever does.
+	pub fn ticks_to_ready(&self, wt_current: usize, recovery_percent: usize) -> Option<usize> {
+		if wt_current == 0 {
+			Some(0)
+		} else if recovery_percent == 0 {
+			None
+		} else {
+			Some((wt_current * 100 - self.hundredths).div_ceil(recovery_percent))
+		}
+	}
+}
+
+// A unit as seen by the scheduler.
+#[derive(Debug, Clone, Copy)]
+pub struct ScheduledUnit {
+	pub unit_id: usize,
+	pub wt_current: usize,
+	pub wt_max: usize,
+	pub recovery_percent: usize,
+	pub progress: WTProgress,
+}
+
+// The unit whose turn it is, if any unit has reached 0 WT.
+pub fn next_ready<'a>(units: impl IntoIterator<Item = &'a ScheduledUnit>) -> Option<usize> {
+	units.into_iter()
+		.filter(|unit| unit.wt_current == 0)
+		.map(|unit| unit.unit_id)
+		.min()
+}
+
+// Ids of the units taking the next `turns` turns, starting with any unit at
+// 0 WT. Units are assumed to keep their current speed, and to both move and
+// act in each of their turns.
+pub fn forecast(mut units: Vec<ScheduledUnit>, turns: usize) -> Vec<usize> {
+	let mut order = Vec::new();
+	while order.len() < turns {
+		if let Some(unit_id) = next_ready(&units) {
+			order.push(unit_id);
+			let unit = units.iter_mut().find(|unit| unit.unit_id == unit_id).unwrap();
+			unit.wt_current = TurnActions { moved: true, acted: true, }.wt_cost(unit.wt_max).max(1);
+		} else {
+			// Skip ahead to the tick where the next unit is ready. Units that
+			// don't recover WT only take the turns they are ready for.
+			let Some(ticks) = units.iter().filter_map(|unit| unit.progress.ticks_to_ready(unit.wt_current, unit.recovery_percent)).min() else {
+				break;
+			};
+			for unit in units.iter_mut() {
+				unit.wt_current = unit.progress.recover_for(unit.wt_current, unit.recovery_percent, ticks);
+			}
+		}
+	}
+	order
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn unit(unit_id: usize, wt_current: usize, wt_max: usize, recovery_percent: usize) -> ScheduledUnit {
+		ScheduledUnit { unit_id, wt_current, wt_max, recovery_percent, progress: WTProgress::default(), }
+	}
+
+	#[test]
+	fn moving_and_acting_make_units_wait_longer() {
+		assert_eq!(TurnActions { moved: false, acted: false, }.wt_cost(100), 60);
+		assert_eq!(TurnActions { moved: true, acted: false, }.wt_cost(100), 80);
+		assert_eq!(TurnActions { moved: false, acted: true, }.wt_cost(100), 80);
+		assert_eq!(TurnActions { moved: true, acted: true, }.wt_cost(100), 100);
+	}
+
+	#[test]
+	fn recovery_follows_agi_and_status() {
+		assert_eq!(recovery_percent(BASE_SPEED, 100), 100);
+		assert_eq!(recovery_percent(BASE_SPEED * 2, 100), 200);
+		assert_eq!(recovery_percent(BASE_SPEED, 50), 50);
+	}
+
+	#[test]
+	fn slow_units_recover_fractions_of_wt() {
+		let mut progress = WTProgress::default();
+
+		assert_eq!(progress.recover(10, 50), 10);
+		assert_eq!(progress.recover(10, 50), 9);
+		assert_eq!(progress.recover(9, 150), 8);
+		assert_eq!(progress.recover(8, 150), 6);
+		assert_eq!(progress.recover(1, 300), 0);
+	}
+
+	#[test]
+	fn recovers_many_ticks_at_once() {
+		let mut progress = WTProgress::default();
+		assert_eq!(progress.recover(10, 50), 10);
+
+		assert_eq!(progress.recover_for(10, 150, 3), 5);
+		assert_eq!(progress.ticks_to_ready(5, 100), Some(5));
+		assert_eq!(progress.ticks_to_ready(5, 0), None);
+		assert_eq!(progress.ticks_to_ready(0, 0), Some(0));
+
+		let mut progress = WTProgress::default();
+		assert_eq!(progress.recover(10, 50), 10);
+		assert_eq!(progress.ticks_to_ready(10, 50), Some(19));
+		assert_eq!(progress.recover_for(10, 50, 19), 0);
+	}
+
+	#[test]
+	fn lowest_unit_id_goes_first_on_ties() {
+		let units = [unit(3, 0, 100, 100), unit(1, 5, 100, 100), unit(2, 0, 100, 100)];
+
+		assert_eq!(next_ready(&units), Some(2));
+		assert_eq!(next_ready(&units[1..2]), None);
+	}
+
+	#[test]
+	fn forecast_lets_faster_units_play_more_often() {
+		let order = forecast(vec![unit(1, 10, 100, 200), unit(2, 10, 100, 100)], 4);
+
+		assert_eq!(order, vec![1, 2, 1, 1]);
+	}
+
+	#[test]
+	fn forecast_stops_when_no_unit_recovers() {
+		let order = forecast(vec![unit(1, 0, 100, 0), unit(2, 10, 100, 0)], FORECAST_TURNS);
+
+		assert_eq!(order, vec![1]);
+	}
+
+	#[test]
+	fn forecast_skips_ahead_to_the_next_turn() {
+		let order = forecast(vec![unit(1, 600, 600, 75), unit(2, 400, 600, 40), unit(3, 0, 600, 0)], 5);
+
+		assert_eq!(order, vec![3, 1, 2, 1, 1]);
+	}
+}